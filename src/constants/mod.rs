@@ -4,6 +4,20 @@ pub const TIMEZONE: chrono_tz::Tz = chrono_tz::Europe::Berlin;
 pub const CONFIG_FILE_NAME: &str = "lamp-server.yaml";
 /// in [`dirs_next::data_dir()`]
 pub const DATA_FILE_NAME: &str = "lamp-server-state.json";
+/// in [`dirs_next::data_dir()`]
+pub const PROGRAMS_FILE_NAME: &str = "lamp-server-programs.json";
+/// in [`dirs_next::data_dir()`]
+pub const SCENES_FILE_NAME: &str = "lamp-server-scenes.json";
+/// in [`dirs_next::data_dir()`]
+pub const COUNTDOWNS_FILE_NAME: &str = "lamp-server-countdowns.json";
+/// in [`dirs_next::data_dir()`]
+pub const TOKENS_FILE_NAME: &str = "lamp-server-tokens.json";
+/// in [`dirs_next::config_dir()`], generated with `lamp-server self-signed-cert`
+pub const CERT_FILE_NAME: &str = "lamp-server-cert.pem";
+/// in [`dirs_next::config_dir()`], generated with `lamp-server self-signed-cert`
+pub const KEY_FILE_NAME: &str = "lamp-server-key.pem";
+/// how many snapshots of the lamp state are kept in memory
+pub const MAX_SNAPSHOTS: usize = 100;
 
 pub mod govee {
     use std::time::Duration;
@@ -14,8 +28,32 @@ pub mod govee {
     /// but calling GET every 6s for 24h is not possible. if you plan to use
     /// GET and PUT increase this interval to > 8s or something.
     pub const API_REQUEST_INTERVAL: Duration = Duration::from_secs(6);
+    /// max number of PUT device state calls per day
+    pub const DAILY_RATE_LIMIT: usize = 10_000;
     /// how long a `set_state()` call usually takes
     pub const AVG_SET_STATE_DURATION: Duration = Duration::from_millis(500);
+    /// color temperature range in Kelvin of models not in [`COLOR_TEMPERATURE_RANGES`]
+    pub const DEFAULT_COLOR_TEMPERATURE_RANGE: (u16, u16) = (2000, 9000);
+    /// estimated color temperature range in Kelvin by model.
+    /// only used until the range of the lamp is fetched from the Govee device list.
+    pub const COLOR_TEMPERATURE_RANGES: &[(&str, (u16, u16))] = &[
+        ("H6003", (2700, 6500)),
+        ("H6008", (2700, 6500)),
+        ("H6009", (2700, 6500)),
+        ("H6010", (2700, 6500)),
+        ("H6046", (2000, 9000)),
+        ("H6104", (2000, 9000)),
+        ("H6159", (2000, 9000)),
+        ("H6163", (2000, 9000)),
+        ("H6199", (2000, 9000)),
+        ("H7022", (2700, 6500)),
+    ];
+}
+
+pub mod reminder {
+    use std::time::Duration;
+    /// how late a reminder may be shown, it is left out otherwise
+    pub const MAX_DELAY: Duration = Duration::from_secs(2 * 60);
 }
 
 pub mod brightness {
@@ -28,6 +66,8 @@ pub mod colors {
     pub const NIGHTLAMP: (u8, u8, u8) = (255, 181, 128);
     pub const DAYLAMP: (u8, u8, u8) = (255, 155, 90);
     pub const REMINDER: (u8, u8, u8) = (255, 100, 0);
+    /// end of sunset
+    pub const SUNSET: (u8, u8, u8) = (255, 60, 0);
 }
 
 // f64 types for easier calculations
@@ -44,10 +84,40 @@ pub mod sunrise {
         pub const START: f64 = 1.0;
         pub const STOP: f64 = 100.0;
     }
+    /// share of the daily rate limit a single sunrise may use
+    pub const MAX_GOVEE_CALLS: usize = 1000;
+    /// color temperature for the blackbody color mode
+    pub mod kelvin {
+        /// deep red of dawn
+        pub const START: f64 = 1800.0;
+        /// daylight
+        pub const STOP: f64 = 5000.0;
+    }
+}
+
+pub mod program {
+    /// latest keyframe of a program, in seconds
+    pub const MAX_OFFSET_SEC: u32 = 24 * 60 * 60;
+    pub const MAX_KEYFRAMES: usize = 1000;
+}
+
+pub mod auth {
+    use std::time::Duration;
+    /// failed authorizations from one address before it is locked out
+    pub const MAX_FAILURES: u32 = 5;
+    /// failures older than this are forgotten
+    pub const FAILURE_WINDOW: Duration = Duration::from_secs(10 * 60);
+    /// how long a locked out address gets no authorization
+    pub const LOCKOUT: Duration = Duration::from_secs(15 * 60);
 }
 
 pub mod net {
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::{net::{IpAddr, Ipv4Addr}, time::Duration};
+    /// default address to serve on, if none is configured
     pub const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
     pub const PORT: u16 = 9000;
+    /// how often the TLS certificate and key are checked for changes
+    pub const TLS_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+    /// connections that don't finish the TLS handshake in time are closed
+    pub const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 }
\ No newline at end of file
@@ -0,0 +1,255 @@
+use utoipa::ToSchema;
+use serde::Serialize;
+use std::{borrow::Cow, sync::{OnceLock, atomic::{AtomicU32, Ordering}}};
+use axum::{
+    Json,
+    extract::Request,
+    middleware::Next,
+    http::{header, HeaderName, HeaderValue, StatusCode as Code},
+    response::{IntoResponse, Response}
+};
+
+/// header with the id of the request in every response
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    /// id of the request currently handled
+    static REQUEST_ID: String;
+}
+/// number of requests handled since the server started
+static REQUESTS: AtomicU32 = AtomicU32::new(0);
+
+/// machine-readable reason of an error, which doesn't change between versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// request was rejected before reaching its route, e.g. because its body is not valid JSON
+    InvalidRequest,
+    /// there is no such route
+    RouteNotFound,
+    /// the route doesn't support the method
+    MethodNotAllowed,
+    /// unexpected error of the server
+    InternalError,
+    /// authorization header is missing
+    MissingAuthorization,
+    /// authorization header is not a bearer token of visible ASCII
+    InvalidAuthorization,
+    /// bearer token is unknown or revoked
+    UnknownToken,
+    /// bearer token doesn't have the scope of the route
+    MissingScope,
+    /// too many failed authorizations from the address
+    LockedOut,
+    /// changing timers requires an `If-Match` header
+    VersionRequired,
+    /// `If-Match` header is not visible ASCII
+    InvalidVersion,
+    /// timers were changed since the version in `If-Match`, see `current_version`
+    TimerVersionMismatch,
+    /// state of the lamp could not be fetched from the Govee API
+    GoveeUnavailable,
+    InvalidColor,
+    InvalidBrightness,
+    InvalidColorTemperature,
+    InvalidDuration,
+    InvalidReminderPattern,
+    /// timer is invalid, see `field`
+    InvalidTimer,
+    InvalidPatch,
+    InvalidRange,
+    InvalidName,
+    InvalidProgram,
+    InvalidScene,
+    InvalidScopes,
+    TimerNotFound,
+    ProgramNotFound,
+    SceneNotFound,
+    SnapshotNotFound,
+    CountdownNotFound,
+    TokenNotFound,
+    /// timer is skipped or paused for all upcoming occurrences
+    NoUpcomingOccurrence,
+    /// snoozing needs an active sunrise or a pending snooze
+    NothingToSnooze,
+    ProgramInUse,
+    SceneInUse,
+    /// built-in scenes can only be overridden
+    BuiltInScene,
+    TokenExists
+}
+
+impl ErrorCode {
+    /// code of a response that was rejected before reaching a route with `status`
+    fn of_rejection(status: Code) -> Self {
+        match status {
+            Code::NOT_FOUND => Self::RouteNotFound,
+            Code::METHOD_NOT_ALLOWED => Self::MethodNotAllowed,
+            status if status.is_server_error() => Self::InternalError,
+            _ => Self::InvalidRequest
+        }
+    }
+}
+
+/// error of a route, responded with an [`ErrorBody`]
+#[derive(Debug)]
+pub struct ApiError {
+    status: Code,
+    code: ErrorCode,
+    message: Cow<'static, str>,
+    field: Option<String>,
+    current_version: Option<String>
+}
+
+impl ApiError {
+    /// error caused by the field at path `field` of the request, like "action.params.duration_min"
+    pub fn field(status: Code, code: ErrorCode, field: impl Into<String>, message: &'static str) -> Self {
+        Self { field: Some(field.into()), ..(status, code, message).into() }
+    }
+
+    /// error because timers were changed since the version the request was based on
+    pub fn version_mismatch(current_version: String) -> Self {
+        let message = format!("timers were changed in the meantime, current version is \"{current_version}\"");
+        Self { current_version: Some(current_version), ..(Code::PRECONDITION_FAILED, ErrorCode::TimerVersionMismatch, message).into() }
+    }
+
+    pub fn status(&self) -> Code {
+        self.status
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.status)
+    }
+}
+
+impl<M: Into<Cow<'static, str>>> From<(Code, ErrorCode, M)> for ApiError {
+    fn from((status, code, message): (Code, ErrorCode, M)) -> Self {
+        Self { status, code, message: message.into(), field: None, current_version: None }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let request_id = REQUEST_ID.try_with(Clone::clone).unwrap_or_default();
+        let body = ErrorBody { error: ErrorDetails {
+            code: self.code,
+            message: self.message.into_owned(),
+            field: self.field,
+            current_version: self.current_version,
+            request_id
+        } };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    error: ErrorDetails
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorDetails {
+    #[schema(example = "invalid_duration")]
+    code: ErrorCode,
+    /// human-readable, may change between versions
+    #[schema(example = "duration_min has to be >= 1")]
+    message: String,
+    /// path of the invalid field of the request, if the error is caused by one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "action.params.duration_min")]
+    field: Option<String>,
+    /// current version of the timers, for `timer_version_mismatch`. same as the `ETag` header.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current_version: Option<String>,
+    /// same as the `X-Request-Id` header
+    request_id: String
+}
+
+
+/// id unique among requests of all runs of the server
+fn next_request_id() -> String {
+    // seconds since epoch when the first request was handled, to differ between restarts
+    static START: OnceLock<i64> = OnceLock::new();
+    let start = START.get_or_init(|| chrono::Utc::now().timestamp());
+    format!("{start:x}-{:x}", REQUESTS.fetch_add(1, Ordering::Relaxed) + 1)
+}
+
+/// axum middleware giving every request an id, which is added to its response as `X-Request-Id` header.
+/// also turns error responses that are not JSON, like rejected requests, into an [`ErrorBody`].
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let request_id = next_request_id();
+    let response = REQUEST_ID.scope(request_id.clone(), next.run(request)).await;
+
+    let is_json = response.headers().get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    let (mut parts, body) = response.into_parts();
+    let body = if (parts.status.is_client_error() || parts.status.is_server_error()) && !is_json {
+        let message = axum::body::to_bytes(body, 64 * 1024).await.unwrap_or_default();
+        let message = match String::from_utf8_lossy(&message) {
+            // like for unknown routes
+            message if message.is_empty() => parts.status.canonical_reason().unwrap_or("error").to_lowercase(),
+            message => message.into_owned()
+        };
+        parts.headers.remove(header::CONTENT_LENGTH);
+        parts.headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        let code = ErrorCode::of_rejection(parts.status);
+        let body = ErrorBody { error: ErrorDetails { code, message, field: None, current_version: None, request_id: request_id.clone() } };
+        axum::body::Body::from(serde_json::to_vec(&body).unwrap())
+    } else {
+        body
+    };
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        parts.headers.insert(REQUEST_ID_HEADER, value);
+    }
+    Response::from_parts(parts, body)
+}
+
+/// utility struct for utoipa to document the `ErrorBody` of every error response and the `X-Request-Id` header
+pub struct ErrorDoc;
+impl utoipa::Modify for ErrorDoc {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        use utoipa::openapi::{Ref, RefOr, ObjectBuilder, Type, content::Content, header::Header};
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [&mut item.get, &mut item.put, &mut item.post, &mut item.delete, &mut item.patch];
+            for operation in operations.into_iter().flatten() {
+                for (status, response) in &mut operation.responses.responses {
+                    let RefOr::T(response) = response else { continue };
+                    if status.starts_with('4') || status.starts_with('5') {
+                        response.content.insert(
+                            String::from("application/json"),
+                            Content::new(Some(Ref::from_schema_name("ErrorBody")))
+                        );
+                    }
+                    let mut id_header = Header::new(ObjectBuilder::new().schema_type(Type::String));
+                    id_header.description = Some(String::from("id of the request, also in error responses"));
+                    response.headers.insert(REQUEST_ID_HEADER.to_string(), id_header);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn error_bodies() {
+        let body = |error: ApiError| async {
+            let response = error.into_response();
+            let body = axum::body::to_bytes(response.into_body(), 1024).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+        let json = body(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidDuration, "duration_min", "message")).await;
+        assert_eq!(json["error"]["code"], "invalid_duration");
+        assert_eq!(json["error"]["field"], "duration_min");
+        assert!(json["error"].get("current_version").is_none());
+        let json = body(ApiError::version_mismatch(String::from("abc"))).await;
+        assert_eq!(json["error"]["code"], "timer_version_mismatch");
+        assert_eq!(json["error"]["current_version"], "abc");
+        assert_eq!(ErrorCode::of_rejection(Code::UNSUPPORTED_MEDIA_TYPE), ErrorCode::InvalidRequest);
+    }
+}
@@ -0,0 +1,173 @@
+use utoipa::ToSchema;
+use std::{collections::BTreeMap, sync::RwLock};
+use chrono::{NaiveDateTime, TimeDelta};
+use serde::{Serialize, Deserialize};
+use crate::constants::COUNTDOWNS_FILE_NAME;
+use crate::control::timer::{self, TimerAction};
+use crate::util::{data_file, fn_queue, timeday};
+
+/// pending countdowns, loaded from [`COUNTDOWNS_FILE_NAME`]
+static COUNTDOWNS: RwLock<Vec<Countdown>> = RwLock::new(vec![]);
+/// steps of the action of each countdown by id, built when its first part is due.
+/// parts of one action share state, like the snapshot a sunrise restores afterwards.
+static STEPS: RwLock<BTreeMap<u32, Vec<Step>>> = RwLock::new(BTreeMap::new());
+
+/// minutes after the first part, description and function of a part of an action
+type Step = (i32, &'static str, fn_queue::Element);
+
+/// action to run once after a delay, like turning the lamp off in 45 minutes
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Countdown {
+    /// assigned by the server, unique among pending countdowns
+    #[schema(read_only)]
+    id: u32,
+    /// when the first part of the action is queued.
+    /// later parts follow like for a timer, e.g. a sunrise with nightlamp starts with the nightlamp.
+    #[schema(value_type = String, read_only, example = "2024-01-01T22:45:00")]
+    starts_at: NaiveDateTime,
+    action: TimerAction,
+    /// how many parts of the action have been queued already
+    #[serde(default)]
+    #[schema(read_only)]
+    parts_done: usize
+}
+
+impl Countdown {
+    /// how many parts of an action with parts at `offsets_min` after the start are due at `now`
+    fn parts_due(&self, offsets_min: &[i32], now: &NaiveDateTime) -> usize {
+        offsets_min.iter()
+            .take_while(|offset_min| self.starts_at + TimeDelta::minutes((**offset_min).into()) <= *now)
+            .count()
+    }
+}
+
+/// load countdowns from file. use none when running into problems.
+pub fn load_countdowns() {
+    match data_file::read::<Vec<Countdown>>(COUNTDOWNS_FILE_NAME) {
+        Ok(countdowns) => {
+            println!("SETUP: successfully loaded {} countdown(s) from file", countdowns.len());
+            *COUNTDOWNS.write().unwrap() = countdowns;
+        },
+        Err(message) => println!("SETUP: {message} for countdowns, using no countdowns...")
+    }
+}
+
+pub fn get_all() -> Vec<Countdown> {
+    COUNTDOWNS.read().unwrap().clone()
+}
+
+/// add countdown running `action` in `delay` and save all countdowns
+pub fn add(delay: TimeDelta, action: TimerAction) -> Countdown {
+    let mut countdowns = COUNTDOWNS.write().unwrap();
+    let countdown = Countdown {
+        id: countdowns.iter().map(|countdown| countdown.id).max().unwrap_or(0) + 1,
+        starts_at: timeday::local_now() + delay,
+        action,
+        parts_done: 0
+    };
+    countdowns.push(countdown.clone());
+    data_file::write(COUNTDOWNS_FILE_NAME, &*countdowns);
+    countdown
+}
+
+/// cancel countdown and save all countdowns. parts that were queued already are not undone.
+/// return whether it existed.
+pub fn remove(id: u32) -> bool {
+    let mut countdowns = COUNTDOWNS.write().unwrap();
+    let length = countdowns.len();
+    countdowns.retain(|countdown| countdown.id != id);
+    let existed = countdowns.len() < length;
+    if existed {
+        STEPS.write().unwrap().remove(&id);
+        data_file::write(COUNTDOWNS_FILE_NAME, &*countdowns);
+    }
+    existed
+}
+
+/// push functions of due parts of countdowns to `function_queue`, and remove finished countdowns.
+/// parts that were due while the server was not running are queued late.
+pub async fn check(function_queue: &fn_queue::Queue) {
+    let due = {
+        let mut countdowns = COUNTDOWNS.write().unwrap();
+        let due = take_due(&mut countdowns, &mut STEPS.write().unwrap(), &timeday::local_now());
+        if !due.is_empty() {
+            data_file::write(COUNTDOWNS_FILE_NAME, &*countdowns);
+        }
+        due
+    };
+    for function in due {
+        fn_queue::enqueue(function_queue, function).await;
+    }
+}
+
+/// functions of the parts of `countdowns` due at `now`, marking them as done and removing finished countdowns.
+/// the steps of each countdown are built once and kept in `steps` until it finished.
+fn take_due(countdowns: &mut Vec<Countdown>, steps: &mut BTreeMap<u32, Vec<Step>>, now: &NaiveDateTime) -> Vec<fn_queue::Element> {
+    let mut due = vec![];
+    let mut finished = vec![];
+    // nothing is due before the start
+    for countdown in countdowns.iter_mut().filter(|countdown| countdown.starts_at <= *now) {
+        let countdown_steps = steps.entry(countdown.id).or_insert_with(|| timer::action_steps(&countdown.action));
+        let offsets_min = countdown_steps.iter().map(|(offset_min, _, _)| *offset_min).collect::<Vec<_>>();
+        let parts_due = countdown.parts_due(&offsets_min, now);
+        if parts_due <= countdown.parts_done {
+            continue;
+        }
+        for (_, description, function) in countdown_steps.iter().take(parts_due).skip(countdown.parts_done) {
+            println!("countdown {} finished, queueing {description}...", countdown.id);
+            due.push(std::sync::Arc::clone(function));
+        }
+        countdown.parts_done = parts_due;
+        if parts_due == offsets_min.len() {
+            finished.push(countdown.id);
+        }
+    }
+    countdowns.retain(|countdown| !finished.contains(&countdown.id));
+    steps.retain(|id, _| !finished.contains(id));
+    due
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parts_follow_start() {
+        let starts_at = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(22, 0, 0).unwrap();
+        let countdown = Countdown { id: 1, starts_at, action: TimerAction::Reminder, parts_done: 0 };
+        // like a sunrise with nightlamp
+        let offsets_min = [0, 60, 570, 600];
+        assert_eq!(countdown.parts_due(&offsets_min, &(starts_at - TimeDelta::seconds(1))), 0);
+        assert_eq!(countdown.parts_due(&offsets_min, &starts_at), 1);
+        assert_eq!(countdown.parts_due(&offsets_min, &(starts_at + TimeDelta::minutes(599))), 3);
+        assert_eq!(countdown.parts_due(&offsets_min, &(starts_at + TimeDelta::days(1))), 4);
+    }
+
+    #[test]
+    fn sunrise_restores_snapshot_of_its_start() {
+        use crate::util::{govee_api::{GetState, SetState}, govee_queue::GoveeQueue};
+        let starts_at = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(6, 0, 0).unwrap();
+        let action = serde_json::from_str(r#"{ "type": "Sunrise", "params":
+            { "duration_min": 1, "stay_on_for_min": 1, "sleep_min": 0, "nightlamp_min": 0, "restore_afterwards": true } }"#).unwrap();
+        let mut countdowns = vec![Countdown { id: 1, starts_at, action, parts_done: 0 }];
+        let mut steps = BTreeMap::new();
+
+        let mut govee_queue = GoveeQueue::default();
+        govee_queue.set_known(&GetState { rgb_color: (1, 2, 3), color_temperature: None, brightness: 40, power: true });
+        let due = take_due(&mut countdowns, &mut steps, &starts_at);
+        assert_eq!(due.len(), 1);
+        due.iter().for_each(|function| function(&mut govee_queue));
+        // checked again while the sunrise runs
+        assert!(take_due(&mut countdowns, &mut steps, &(starts_at + TimeDelta::minutes(1))).is_empty());
+
+        let mut govee_queue = GoveeQueue::default();
+        let due = take_due(&mut countdowns, &mut steps, &(starts_at + TimeDelta::minutes(2)));
+        due.iter().for_each(|function| function(&mut govee_queue));
+        assert_eq!(
+            govee_queue.iter().copied().collect::<Vec<_>>(),
+            [SetState::Power(true), SetState::Brightness(40), SetState::Color((1, 2, 3))]
+        );
+        assert!(countdowns.is_empty());
+        assert!(steps.is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use std::{collections::BTreeMap, net::{IpAddr, Ipv6Addr}, sync::Mutex, time::{Duration, Instant}};
+use crate::constants::auth::{MAX_FAILURES, FAILURE_WINDOW, LOCKOUT};
+
+/// recent failed authorizations by source address (IPv6 by /64 network), kept in memory only
+static FAILURES: Mutex<BTreeMap<IpAddr, Failures>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    /// failures since `since`
+    count: u32,
+    /// first failure in the current window
+    since: Instant,
+    /// set once `count` reaches [`MAX_FAILURES`]
+    locked_until: Option<Instant>
+}
+
+impl Failures {
+    /// whether these failures don't matter anymore at `now`
+    fn expired(&self, now: Instant) -> bool {
+        match self.locked_until {
+            Some(locked_until) => locked_until <= now,
+            None => self.since + FAILURE_WINDOW <= now
+        }
+    }
+}
+
+/// source address of a request from `peer`. behind a trusted proxy, the last address in
+/// `forwarded_for` (the `X-Forwarded-For` header) that is not a trusted proxy itself.
+pub fn client_address(peer: IpAddr, forwarded_for: Option<&str>, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let peer = peer.to_canonical();
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+    // proxies append the address they got the request from, earlier ones may be made up by the client
+    forwarded_for.into_iter()
+        .flat_map(|header| header.rsplit(','))
+        .map_while(|address| address.trim().parse::<IpAddr>().ok().map(|address| address.to_canonical()))
+        .find(|address| !trusted_proxies.contains(address))
+        .unwrap_or(peer)
+}
+
+/// addresses failures are counted for. IPv6 hosts usually get a whole /64 network.
+fn key(address: IpAddr) -> IpAddr {
+    match address.to_canonical() {
+        IpAddr::V6(address) => IpAddr::V6(Ipv6Addr::from_bits(address.to_bits() & !u128::from(u64::MAX))),
+        address @ IpAddr::V4(_) => address
+    }
+}
+
+/// how long `address` is still locked out, none if it may try to authorize
+pub fn locked_for(address: IpAddr) -> Option<Duration> {
+    locked_for_at(&FAILURES.lock().unwrap(), key(address), Instant::now())
+}
+
+/// count a failed authorization from `address`. return whether it is locked out now.
+pub fn record_failure(address: IpAddr) -> bool {
+    record_failure_at(&mut FAILURES.lock().unwrap(), key(address), Instant::now())
+}
+
+/// forget failed authorizations from `address`
+pub fn record_success(address: IpAddr) {
+    FAILURES.lock().unwrap().remove(&key(address));
+}
+
+fn locked_for_at(failures: &BTreeMap<IpAddr, Failures>, address: IpAddr, now: Instant) -> Option<Duration> {
+    failures.get(&address)
+        .and_then(|failures| failures.locked_until)
+        .and_then(|locked_until| locked_until.checked_duration_since(now))
+        .filter(|duration| !duration.is_zero())
+}
+
+fn record_failure_at(failures: &mut BTreeMap<IpAddr, Failures>, address: IpAddr, now: Instant) -> bool {
+    // keep the map small when many addresses fail
+    failures.retain(|_, failures| !failures.expired(now));
+    let entry = failures.entry(address)
+        .or_insert(Failures { count: 0, since: now, locked_until: None });
+    entry.count += 1;
+    if entry.count >= MAX_FAILURES {
+        entry.locked_until = Some(now + LOCKOUT);
+    }
+    entry.locked_until.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locks_out_after_failures() {
+        let mut failures = BTreeMap::new();
+        let address = IpAddr::from([192, 168, 0, 2]);
+        let now = Instant::now();
+        for _ in 1..MAX_FAILURES {
+            assert!(!record_failure_at(&mut failures, address, now));
+        }
+        assert_eq!(locked_for_at(&failures, address, now), None);
+        assert!(record_failure_at(&mut failures, address, now));
+        assert_eq!(locked_for_at(&failures, address, now), Some(LOCKOUT));
+        assert_eq!(locked_for_at(&failures, IpAddr::from([192, 168, 0, 3]), now), None);
+        assert_eq!(locked_for_at(&failures, address, now + LOCKOUT), None);
+        // failures are forgotten after the window
+        record_failure_at(&mut failures, address, now + LOCKOUT);
+        assert!(!record_failure_at(&mut failures, address, now + LOCKOUT + FAILURE_WINDOW));
+        assert_eq!(failures[&address].count, 1);
+    }
+
+    #[test]
+    fn addresses_of_clients() {
+        let proxy = IpAddr::from([127, 0, 0, 1]);
+        let client = IpAddr::from([192, 168, 0, 2]);
+        assert_eq!(client_address(client, Some("10.0.0.1"), &[proxy]), client);
+        assert_eq!(client_address(proxy, None, &[proxy]), proxy);
+        // the client can't make up an address in front of the one the proxy saw
+        assert_eq!(client_address(proxy, Some("10.0.0.1, 192.168.0.2"), &[proxy]), client);
+        assert_eq!(client_address("::ffff:127.0.0.1".parse().unwrap(), Some("192.168.0.2"), &[proxy]), client);
+
+        let v6 = |address: &str| address.parse::<IpAddr>().unwrap();
+        assert_eq!(key(v6("2001:db8:1:2:aaaa::1")), key(v6("2001:db8:1:2:bbbb::2")));
+        assert_ne!(key(v6("2001:db8:1:2::1")), key(v6("2001:db8:1:3::1")));
+        assert_eq!(key(client), client);
+    }
+}
@@ -1,21 +1,36 @@
 pub mod web;
+pub mod api_error;
 pub mod state;
 pub mod timer;
+pub mod program;
+pub mod scene;
+pub mod countdown;
+pub mod snapshot;
+pub mod token;
+pub mod lockout;
+pub mod validation;
 
 /// one time setup
 #[allow(clippy::assertions_on_constants)]
 pub fn setup() {
-    use crate::util::govee_secrets;
+    use crate::util::{config, govee_secrets};
     use crate::constants::sunrise::{govee_brightness, hsv_color};
 
     // check sunrise constants
     assert!(govee_brightness::START < govee_brightness::STOP, "sunrise brightness has to start smaller than it stops");
     assert!(hsv_color::saturation::START > hsv_color::saturation::STOP, "sunrise color saturation has to start larger than it stops");
 
-    // read govee secrets from config file
+    // read govee secrets and other settings from config file
     govee_secrets::INSTANCE.set(govee_secrets::from_file()).unwrap();
+    config::INSTANCE.set(config::from_file()).unwrap();
     println!("SETUP: successfully loaded config from file");
 
+    // timers may use programs and scenes, so load them first
+    program::load_programs();
+    scene::load_scenes();
+    countdown::load_countdowns();
+    token::load_tokens();
+
     // check debug mode
     if cfg!(feature = "govee_debug") {
         println!("SETUP: GOVEE_DEBUG is enabled => not sending PUT requests to Govee API");
@@ -25,15 +40,29 @@ pub fn setup() {
 /// never terminates
 pub async fn main_loop() {
     use tokio::sync::Mutex;
-    use timer::SimpleTimers;
-    use std::{collections::VecDeque, sync::Arc, thread::sleep};
+    use timer::{SimpleTimers, Timers};
+    use std::{collections::VecDeque, sync::Arc};
+    use tokio::time::sleep;
     use crate::constants::govee::API_REQUEST_INTERVAL;
-    use crate::util::{fn_queue, timeday::TimeDay, govee_api::{self, SetState}};
+    use crate::util::{fn_queue, timeday::{self, TimeDay}, govee_api, govee_queue::GoveeQueue};
 
     setup();
 
+    // the lamp might support a different range than estimated by its model
+    match govee_api::fetch_color_temperature_range().await {
+        Ok((min, max)) => println!("SETUP: lamp supports color temperatures from {min} to {max} Kelvin"),
+        Err(()) => println!("SETUP: could not get color temperature range of the lamp, using estimate {:?}...", govee_api::color_temperature_range())
+    }
+    crate::util::config::check_color_temperatures();
+
     // queue of `SetState`s of which the first one will be used for a Govee API call each iteration
-    let mut govee_queue: VecDeque<SetState> = VecDeque::new();
+    let mut govee_queue = GoveeQueue::default();
+    // known state of the lamp, updated with every state set, e.g. for snapshots without fetching.
+    // spaced like all other requests to avoid reaching rate limits
+    sleep(API_REQUEST_INTERVAL).await;
+    if let Ok(state) = govee_api::get_state().await {
+        govee_queue.set_known(&state);
+    }
 
     // queue of functions to be called once at the start of the next loop.
     // all functions will be called and then removed from the queue, starting from the front.
@@ -45,34 +74,52 @@ pub async fn main_loop() {
     // if a timer matches the current time its function will be pushed to the function queue.
     let simple_timers: SimpleTimers = Arc::new(Mutex::new(vec![]));
 
+    // higher level timers which will be converted and pushed to `simple_timers`
+    let timers: Timers = timer::load_timers(&simple_timers).await;
+    // timers are processed again when the date changes, to recompute e.g. solar times
+    let mut last_processed_date = timeday::local_now().date();
+
     // will be updated by timer::check_timers() to avoid matching timers more than once per minute
     let mut last_checked_time = TimeDay::now().shift_time(0, -1);
+    // running, delayed and left out timer occurrences to enforce conflict policies
+    let mut conflicts = timer::Conflicts::default();
 
     // start webserver ("fire and forget" instead of "await")
     tokio::spawn(web::start_server(
         Arc::clone(&function_queue),
+        Arc::clone(&timers),
         Arc::clone(&simple_timers)
     ));
 
     // wait before starting loop to avoid reaching rate limits when restarting frequently
-    sleep(API_REQUEST_INTERVAL);
+    sleep(API_REQUEST_INTERVAL).await;
 
     // actual main loop
     loop {
-        timer::check_timers(&simple_timers, &function_queue, &mut last_checked_time).await;
+        let today = timeday::local_now().date();
+        if today != last_processed_date {
+            println!("date changed, processing timers again...");
+            timer::process_timers(&timers, &simple_timers).await;
+            last_processed_date = today;
+        }
+
+        timer::check_timers(&simple_timers, &function_queue, &mut last_checked_time, &mut conflicts).await;
+        countdown::check(&function_queue).await;
 
         fn_queue::call_all(&function_queue, &mut govee_queue).await;
 
-        if !govee_queue.is_empty() {
-            let success = govee_api::set_state(*govee_queue.front().unwrap()).await;
+        if let Some(state) = govee_queue.front_due(&timeday::local_now()) {
+            let success = govee_api::set_state(state).await;
             if success {
                 govee_queue.pop_front();
             } else {
                 println!("setting state failed, trying again");
             }
+        } else if !govee_queue.is_empty() {
+            println!("waiting for next planned state");
         }
 
         println!("----- waiting -----");
-        sleep(API_REQUEST_INTERVAL);
+        sleep(API_REQUEST_INTERVAL).await;
     }
 }
\ No newline at end of file
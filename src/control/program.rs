@@ -0,0 +1,245 @@
+use utoipa::ToSchema;
+use itertools::Itertools;
+use std::{collections::BTreeMap, sync::RwLock, time::Duration};
+use serde::{Serialize, Deserialize};
+use crate::util::{data_file, govee_api::SetState, govee_queue::GoveeQueue};
+
+/// all programs by name, loaded from [`crate::constants::PROGRAMS_FILE_NAME`]
+static PROGRAMS: RwLock<BTreeMap<String, Program>> = RwLock::new(BTreeMap::new());
+
+/// brightness, color and power, each if known
+type Values = (Option<u8>, Option<(u8, u8, u8)>, Option<bool>);
+
+/// state of an expanded program and when it is set after the start
+pub type Step = (Duration, SetState);
+
+/// state of the lamp at a point in time of a `Program`.
+/// values that are not given stay as they were.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Keyframe {
+    /// seconds since the start of the program, at most a day
+    #[schema(maximum = 86400)]
+    offset_sec: u32,
+    /// each value is from 0 to 255
+    #[serde(default)]
+    #[schema(min_items = 3, max_items = 3)]
+    color: Option<(u8, u8, u8)>,
+    #[serde(default)]
+    #[schema(minimum = 1, maximum = 100)]
+    brightness: Option<u8>,
+    #[serde(default)]
+    power: Option<bool>
+}
+
+/// user-defined light sequence. color and brightness are interpolated linearly between keyframes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Program {
+    /// ascending by offset, starting at offset 0. at most 1000.
+    #[schema(max_items = 1000)]
+    keyframes: Vec<Keyframe>
+}
+
+impl Program {
+    /// return error message if program is invalid
+    pub fn validate(&self) -> Result<(), &'static str> {
+        use crate::constants::program::{MAX_KEYFRAMES, MAX_OFFSET_SEC};
+        if self.keyframes.len() > MAX_KEYFRAMES {
+            return Err("there must be at most 1000 keyframes");
+        }
+        let Some(first) = self.keyframes.first() else {
+            return Err("keyframes must not be empty");
+        };
+        if first.offset_sec != 0 {
+            return Err("first keyframe has to have offset_sec 0");
+        }
+        if !self.keyframes.iter().tuple_windows().all(|(a, b)| a.offset_sec < b.offset_sec) {
+            return Err("offset_sec of keyframes has to be strictly ascending");
+        }
+        if self.keyframes.iter().any(|k| k.offset_sec > MAX_OFFSET_SEC) {
+            return Err("offset_sec of keyframes has to be at most 86400 (a day)");
+        }
+        if self.keyframes.iter().filter_map(|k| k.brightness).any(|b| !(1 ..= 100).contains(&b)) {
+            return Err("brightness of every keyframe has to be from 1 to 100");
+        }
+        Ok(())
+    }
+
+    /// brightness and color at `offset` (interpolated), and power of the last keyframe setting it.
+    /// none for values no keyframe until `offset` has.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn state_at(&self, offset: f64) -> Values {
+        // value of the last keyframe at or before `offset` having it,
+        // interpolated towards the next keyframe having it
+        let interpolate = |value: &dyn Fn(&Keyframe) -> Option<f64>| {
+            let previous = self.keyframes.iter()
+                .rev().filter(|k| f64::from(k.offset_sec) <= offset)
+                .find_map(|k| value(k).map(|v| (f64::from(k.offset_sec), v)))?;
+            let next = self.keyframes.iter()
+                .filter(|k| f64::from(k.offset_sec) > offset)
+                .find_map(|k| value(k).map(|v| (f64::from(k.offset_sec), v)));
+            Some(next.map_or(previous.1, |next| {
+                let progress = (offset - previous.0) / (next.0 - previous.0);
+                (next.1 - previous.1).mul_add(progress, previous.1)
+            }).round())
+        };
+
+        let brightness = interpolate(&|k| k.brightness.map(f64::from)).map(|b| b as u8);
+        let color = interpolate(&|k| k.color.map(|c| f64::from(c.0))).and_then(|r|
+            interpolate(&|k| k.color.map(|c| f64::from(c.1))).and_then(|g|
+                interpolate(&|k| k.color.map(|c| f64::from(c.2))).map(|b| (r as u8, g as u8, b as u8))));
+        let power = self.keyframes.iter()
+            .filter(|k| f64::from(k.offset_sec) <= offset)
+            .filter_map(|k| k.power)
+            .next_back();
+        (brightness, color, power)
+    }
+
+    /// states planned one per API request interval, by offset after the start.
+    /// power changes come first, brightness and color alternate otherwise.
+    /// slots in which nothing changes are left out.
+    pub fn expand(&self) -> Vec<Step> {
+        use crate::constants::govee::{API_REQUEST_INTERVAL, AVG_SET_STATE_DURATION};
+        let interval = (API_REQUEST_INTERVAL + AVG_SET_STATE_DURATION).as_secs_f64();
+        let end = f64::from(self.keyframes.last().map_or(0, |k| k.offset_sec));
+
+        let mut steps = vec![];
+        let (mut brightness, mut color, mut power) = (None, None, None);
+        for slot in 0u32 .. {
+            let offset = f64::from(slot) * interval;
+            let (target_brightness, target_color, target_power) = self.state_at(offset.min(end));
+
+            let brightness_changed = power != Some(false) && target_brightness.is_some() && target_brightness != brightness;
+            let color_changed = power != Some(false) && target_color.is_some() && target_color != color;
+            let state = if target_power.is_some() && target_power != power {
+                power = target_power;
+                target_power.map(SetState::Power)
+            } else if brightness_changed && (slot % 2 == 0 || !color_changed) {
+                brightness = target_brightness;
+                target_brightness.map(SetState::Brightness)
+            } else if color_changed {
+                color = target_color;
+                target_color.map(SetState::Color)
+            } else if offset < end {
+                None
+            } else {
+                // reached end and every value is set
+                break;
+            };
+            if let Some(state) = state {
+                steps.push((Duration::from_secs_f64(offset), state));
+            }
+        }
+        steps
+    }
+}
+
+/// estimated duration of setting expanded `steps`
+pub fn duration(steps: &[Step]) -> Duration {
+    use crate::constants::govee::{API_REQUEST_INTERVAL, AVG_SET_STATE_DURATION};
+    steps.last().map_or(Duration::ZERO, |(offset, _)| *offset + API_REQUEST_INTERVAL + AVG_SET_STATE_DURATION)
+}
+
+/// load programs from file. use no programs when running into problems.
+pub fn load_programs() {
+    match data_file::read::<BTreeMap<String, Program>>(crate::constants::PROGRAMS_FILE_NAME) {
+        Ok(programs) => {
+            println!("SETUP: successfully loaded {} program(s) from file", programs.len());
+            *PROGRAMS.write().unwrap() = programs;
+        },
+        Err(message) => println!("SETUP: {message} for programs, using no programs...")
+    }
+}
+
+pub fn get_all() -> BTreeMap<String, Program> {
+    PROGRAMS.read().unwrap().clone()
+}
+
+pub fn get(name: &str) -> Option<Program> {
+    PROGRAMS.read().unwrap().get(name).cloned()
+}
+
+/// add or replace program and save all programs. return whether it was added.
+pub fn set(name: String, program: Program) -> bool {
+    let mut programs = PROGRAMS.write().unwrap();
+    let added = programs.insert(name, program).is_none();
+    data_file::write(crate::constants::PROGRAMS_FILE_NAME, &*programs);
+    added
+}
+
+/// remove program and save all programs. return whether it existed.
+pub fn remove(name: &str) -> bool {
+    let mut programs = PROGRAMS.write().unwrap();
+    let existed = programs.remove(name).is_some();
+    data_file::write(crate::constants::PROGRAMS_FILE_NAME, &*programs);
+    existed
+}
+
+/// append the expanded states of program `name` to `govee_queue` starting now, if it exists
+pub fn run(govee_queue: &mut GoveeQueue, name: &str) {
+    let Some(program) = get(name) else {
+        println!("program {name:?} doesn't exist anymore, ignoring");
+        return;
+    };
+    let steps = program.expand();
+    println!("running program {name:?} with {} states", steps.len());
+    push_states(govee_queue, &steps);
+}
+
+/// append expanded states of a program as a sequence starting now,
+/// so a delayed program catches up instead of running late
+pub fn push_states(govee_queue: &mut GoveeQueue, steps: &[Step]) {
+    govee_queue.push_sequence(crate::util::timeday::local_now(), steps.iter().copied());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(json: &str) -> Program {
+        let program = serde_json::from_str::<Program>(json).unwrap();
+        program.validate().unwrap();
+        program
+    }
+
+    #[test]
+    fn interpolates_between_keyframes() {
+        let program = program(r#"{ "keyframes": [
+            { "offset_sec": 0, "brightness": 1, "color": [0, 0, 0], "power": true },
+            { "offset_sec": 100, "brightness": 51, "color": [200, 100, 0] },
+            { "offset_sec": 200, "color": [200, 0, 0] }
+        ] }"#);
+        assert_eq!(program.state_at(0.0), (Some(1), Some((0, 0, 0)), Some(true)));
+        assert_eq!(program.state_at(50.0), (Some(26), Some((100, 50, 0)), Some(true)));
+        // brightness is held after its last keyframe
+        assert_eq!(program.state_at(150.0), (Some(51), Some((200, 50, 0)), Some(true)));
+    }
+
+    #[test]
+    fn expand_keeps_timing_and_ends_with_last_keyframe() {
+        let program = program(r#"{ "keyframes": [
+            { "offset_sec": 0, "brightness": 100, "color": [255, 0, 0], "power": true },
+            { "offset_sec": 65, "brightness": 100 },
+            { "offset_sec": 130, "power": false }
+        ] }"#);
+        // unchanged slots in between are left out
+        assert_eq!(program.expand(), [
+            (Duration::ZERO, SetState::Power(true)),
+            (Duration::from_millis(6500), SetState::Color((255, 0, 0))),
+            (Duration::from_secs(13), SetState::Brightness(100)),
+            (Duration::from_secs(130), SetState::Power(false))
+        ]);
+        assert_eq!(duration(&program.expand()), Duration::from_millis(136_500));
+    }
+
+    #[test]
+    fn invalid_programs() {
+        let invalid = |json| serde_json::from_str::<Program>(json).unwrap().validate().is_err();
+        assert!(invalid(r#"{ "keyframes": [] }"#));
+        assert!(invalid(r#"{ "keyframes": [{ "offset_sec": 5 }] }"#));
+        assert!(invalid(r#"{ "keyframes": [{ "offset_sec": 0 }, { "offset_sec": 0 }] }"#));
+        assert!(invalid(r#"{ "keyframes": [{ "offset_sec": 0, "brightness": 0 }] }"#));
+        assert!(invalid(r#"{ "keyframes": [{ "offset_sec": 0 }, { "offset_sec": 4000000000 }] }"#));
+        let many = (0 ..= 1000).map(|offset| format!(r#"{{ "offset_sec": {offset} }}"#)).join(",");
+        assert!(invalid(&format!(r#"{{ "keyframes": [{many}] }}"#)));
+    }
+}
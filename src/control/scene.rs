@@ -0,0 +1,177 @@
+use utoipa::ToSchema;
+use std::{collections::BTreeMap, sync::RwLock};
+use serde::{Serialize, Deserialize};
+use crate::constants::{self, brightness, colors};
+use crate::util::{config, data_file, govee_api::{self, SetState}, govee_queue::GoveeQueue};
+
+/// user-defined scenes by name, loaded from [`crate::constants::SCENES_FILE_NAME`].
+/// they override built-in scenes with the same name.
+static SCENES: RwLock<BTreeMap<String, Scene>> = RwLock::new(BTreeMap::new());
+
+/// built-in scene for night, also used after a sunset
+pub const NIGHTLAMP: &str = "nightlamp";
+/// built-in scene for day, also used after a sunrise
+pub const DAYLAMP: &str = "daylamp";
+/// built-in scene shown by reminders
+pub const REMINDER: &str = "reminder";
+
+/// state of the lamp to activate at once. values that are not given stay as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Scene {
+    /// each value is from 0 to 255. can't be combined with `color_temperature`.
+    #[serde(default)]
+    #[schema(min_items = 3, max_items = 3)]
+    color: Option<(u8, u8, u8)>,
+    /// in Kelvin, the supported range depends on the lamp model
+    #[serde(default)]
+    #[schema(minimum = 1000, maximum = 10000)]
+    color_temperature: Option<u16>,
+    #[serde(default)]
+    #[schema(minimum = 1, maximum = 100)]
+    brightness: Option<u8>,
+    #[serde(default)]
+    power: Option<bool>
+}
+
+impl Scene {
+    /// return error message if scene is invalid
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.color.is_none() && self.color_temperature.is_none() && self.brightness.is_none() && self.power.is_none() {
+            return Err("scene has to set at least one of color, color_temperature, brightness and power");
+        }
+        if self.color.is_some() && self.color_temperature.is_some() {
+            return Err("scene can't set both color and color_temperature");
+        }
+        if self.brightness.is_some_and(|b| !(1 ..= 100).contains(&b)) {
+            return Err("brightness has to be from 1 to 100");
+        }
+        if self.color_temperature.is_some_and(|kelvin| !govee_api::color_temperature_range().contains(&kelvin)) {
+            return Err("color_temperature is out of the color temperature range of the lamp model");
+        }
+        Ok(())
+    }
+
+    /// states to activate this scene: power on, brightness, color and power off, as far as they are given
+    pub fn states(&self) -> Vec<SetState> {
+        [
+            self.power.filter(|power| *power).map(SetState::Power),
+            self.brightness.map(SetState::Brightness),
+            self.color.map(SetState::Color),
+            self.color_temperature.map(SetState::ColorTemperature),
+            self.power.filter(|power| !power).map(SetState::Power),
+        ].into_iter().flatten().collect()
+    }
+
+    /// brightness of this scene, if it sets one
+    pub const fn brightness(&self) -> Option<u8> {
+        self.brightness
+    }
+
+    /// RGB color of this scene, approximated if it sets a color temperature
+    pub fn rgb_color(&self) -> Option<(u8, u8, u8)> {
+        self.color.or_else(|| self.color_temperature.map(crate::util::kelvin::to_rgb))
+    }
+}
+
+/// scene with given name shipped with the server.
+/// nightlamp and daylamp use the color temperatures from the config file if set.
+pub fn builtin(name: &str) -> Option<Scene> {
+    let lamp = |brightness, color, color_temperature: Option<u16>| Scene {
+        color: if color_temperature.is_some() { None } else { Some(color) },
+        color_temperature,
+        brightness: Some(brightness),
+        power: None
+    };
+    match name {
+        NIGHTLAMP => Some(lamp(brightness::NIGHT, colors::NIGHTLAMP, config::nightlamp_color_temperature())),
+        DAYLAMP => Some(lamp(brightness::DAY, colors::DAYLAMP, config::daylamp_color_temperature())),
+        REMINDER => Some(lamp(brightness::REMINDER, colors::REMINDER, None)),
+        _ => None
+    }
+}
+
+/// load user-defined scenes from file. use none when running into problems.
+pub fn load_scenes() {
+    match data_file::read::<BTreeMap<String, Scene>>(constants::SCENES_FILE_NAME) {
+        Ok(scenes) => {
+            println!("SETUP: successfully loaded {} scene(s) from file", scenes.len());
+            *SCENES.write().unwrap() = scenes;
+        },
+        Err(message) => println!("SETUP: {message} for scenes, using built-in scenes only...")
+    }
+}
+
+/// built-in scenes, overridden by user-defined ones
+pub fn get_all() -> BTreeMap<String, Scene> {
+    let mut scenes = [NIGHTLAMP, DAYLAMP, REMINDER].into_iter()
+        .filter_map(|name| builtin(name).map(|scene| (name.to_owned(), scene)))
+        .collect::<BTreeMap<_, _>>();
+    scenes.extend(SCENES.read().unwrap().clone());
+    scenes
+}
+
+/// user-defined scene with given name, or else built-in scene
+pub fn get(name: &str) -> Option<Scene> {
+    SCENES.read().unwrap().get(name).cloned().or_else(|| builtin(name))
+}
+
+/// add or replace user-defined scene and save all of them. return whether it was added.
+pub fn set(name: String, scene: Scene) -> bool {
+    let mut scenes = SCENES.write().unwrap();
+    let added = scenes.insert(name, scene).is_none();
+    data_file::write(constants::SCENES_FILE_NAME, &*scenes);
+    added
+}
+
+/// remove user-defined scene and save all of them. return whether it existed.
+pub fn remove(name: &str) -> bool {
+    let mut scenes = SCENES.write().unwrap();
+    let existed = scenes.remove(name).is_some();
+    data_file::write(constants::SCENES_FILE_NAME, &*scenes);
+    existed
+}
+
+/// number of states `activate` appends for scene `name`
+pub fn state_amount(name: &str) -> usize {
+    get(name).map_or(0, |scene| scene.states().len())
+}
+
+/// append the states of scene `name` to `govee_queue`, if it exists
+pub fn activate(govee_queue: &mut GoveeQueue, name: &str) {
+    let Some(scene) = get(name) else {
+        println!("scene {name:?} doesn't exist anymore, ignoring");
+        return;
+    };
+    println!("activating scene {name:?}...");
+    for state in scene.states() {
+        govee_queue.push_back(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_scenes_match_old_presets() {
+        let nightlamp = builtin(NIGHTLAMP).unwrap();
+        assert_eq!(nightlamp.states(), [SetState::Brightness(brightness::NIGHT), SetState::Color(colors::NIGHTLAMP)]);
+        assert_eq!(builtin(REMINDER).unwrap().rgb_color(), Some(colors::REMINDER));
+        assert!(builtin("unknown").is_none());
+    }
+
+    #[test]
+    fn scene_states_and_validation() {
+        let scene = |json| serde_json::from_str::<Scene>(json).unwrap();
+        assert_eq!(
+            scene(r#"{ "color_temperature": 2700, "brightness": 20, "power": true }"#).states(),
+            [SetState::Power(true), SetState::Brightness(20), SetState::ColorTemperature(2700)]
+        );
+        assert_eq!(scene(r#"{ "power": false }"#).states(), [SetState::Power(false)]);
+
+        assert!(scene(r#"{ "brightness": 20 }"#).validate().is_ok());
+        assert!(scene("{}").validate().is_err());
+        assert!(scene(r#"{ "color": [255, 0, 0], "color_temperature": 2700 }"#).validate().is_err());
+        assert!(scene(r#"{ "brightness": 101 }"#).validate().is_err());
+    }
+}
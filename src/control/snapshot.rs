@@ -0,0 +1,88 @@
+use utoipa::ToSchema;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::{atomic::{AtomicU32, Ordering}, RwLock}};
+use crate::constants::MAX_SNAPSHOTS;
+use crate::util::{timeday, govee_api::{GetState, SetState}, govee_queue::GoveeQueue};
+
+/// latest snapshots by id, kept in memory only
+static SNAPSHOTS: RwLock<BTreeMap<u32, Snapshot>> = RwLock::new(BTreeMap::new());
+/// last id given to a snapshot
+static LAST_ID: AtomicU32 = AtomicU32::new(0);
+
+/// state of the lamp captured before a temporary action, to get back to it afterwards
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct Snapshot {
+    pub id: u32,
+    #[schema(value_type = String, example = "2024-01-01T06:00:00")]
+    pub taken_at: NaiveDateTime,
+    pub state: GetState
+}
+
+/// keep `state` as new snapshot.
+/// only the latest [`MAX_SNAPSHOTS`] snapshots are kept.
+pub fn take(state: GetState) -> Snapshot {
+    let snapshot = Snapshot {
+        id: LAST_ID.fetch_add(1, Ordering::Relaxed) + 1,
+        taken_at: timeday::local_now(),
+        state
+    };
+    let mut snapshots = SNAPSHOTS.write().unwrap();
+    snapshots.insert(snapshot.id, snapshot);
+    while snapshots.len() > MAX_SNAPSHOTS {
+        snapshots.pop_first();
+    }
+    println!("took snapshot {} of {:?}", snapshot.id, snapshot.state);
+    snapshot
+}
+
+/// take a snapshot of the state the lamp has after the states in `govee_queue` are set,
+/// so states queued right before are included. none if that state is not fully known.
+pub fn take_expected(govee_queue: &GoveeQueue) -> Option<Snapshot> {
+    let state = govee_queue.expected().state();
+    if state.is_none() {
+        println!("state of the lamp is not fully known, could not take snapshot");
+    }
+    state.map(take)
+}
+
+pub fn get(id: u32) -> Option<Snapshot> {
+    SNAPSHOTS.read().unwrap().get(&id).copied()
+}
+
+/// states to get back to `previous`, turning on first in case the lamp is off now
+pub fn restore_states(previous: &GetState) -> Vec<SetState> {
+    if !previous.power {
+        return vec![SetState::Power(false)];
+    }
+    vec![
+        SetState::Power(true),
+        SetState::Brightness(previous.brightness),
+        previous.color_temperature.map_or(SetState::Color(previous.rgb_color), SetState::ColorTemperature)
+    ]
+}
+
+/// append states to get back to snapshot `id`. return whether it exists.
+pub fn restore(govee_queue: &mut GoveeQueue, id: u32) -> bool {
+    let Some(snapshot) = get(id) else {
+        return false;
+    };
+    println!("restoring snapshot {id}...");
+    for state in restore_states(&snapshot.state) {
+        govee_queue.push_back(state);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_power_brightness_and_color() {
+        let state = |power, color_temperature| GetState { rgb_color: (1, 2, 3), color_temperature, brightness: 40, power };
+        assert_eq!(restore_states(&state(true, None)), [SetState::Power(true), SetState::Brightness(40), SetState::Color((1, 2, 3))]);
+        assert_eq!(restore_states(&state(true, Some(2700))), [SetState::Power(true), SetState::Brightness(40), SetState::ColorTemperature(2700)]);
+        assert_eq!(restore_states(&state(false, Some(2700))), [SetState::Power(false)]);
+    }
+}
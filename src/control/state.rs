@@ -1,67 +1,523 @@
-use std::{time::Duration, collections::VecDeque};
+use std::{sync::atomic::{AtomicU32, Ordering}, time::Duration};
 use crate::constants;
-use crate::util::govee_api::SetState;
+use crate::control::{scene, snapshot};
+use crate::util::{config, curve::Curve, reminder::{ReminderAfterwards, ReminderPattern}, kelvin, timeday, govee_api::{self, SetState}, govee_queue::{self, Entry, GoveeQueue, Group, Shadow}};
 
-/// set brightness to default for night and color to nice warm white
-pub fn nightlamp(govee_queue: &mut VecDeque<SetState>) {
-    use constants::{brightness::NIGHT, colors::NIGHTLAMP};
-    println!("activating nightlamp...");
-    govee_queue.push_back(SetState::Brightness(NIGHT));
-    govee_queue.push_back(SetState::Color(NIGHTLAMP));
+/// sequence of the last sunrise queued, 0 if none was
+static SUNRISE_SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+/// activate nightlamp scene, by default brightness for night and nice warm white color
+pub fn nightlamp(govee_queue: &mut GoveeQueue) {
+    scene::activate(govee_queue, scene::NIGHTLAMP);
 }
 
-/// set brightness to default for day and color to pleasant orange
-pub fn daylamp(govee_queue: &mut VecDeque<SetState>) {
-    use constants::{brightness::DAY, colors::DAYLAMP};
-    println!("activating daylamp...");
-    govee_queue.push_back(SetState::Brightness(DAY));
-    govee_queue.push_back(SetState::Color(DAYLAMP));
+/// activate daylamp scene, by default brightness for day and pleasant orange color
+pub fn daylamp(govee_queue: &mut GoveeQueue) {
+    scene::activate(govee_queue, scene::DAYLAMP);
+}
+
+/// states of a reminder with `pattern` and the slot each is planned in, and whether it shows the reminder.
+/// states showing it may expire, the others are only left out if none of them was set.
+/// `on` turns the reminder on the first time, `afterwards` follows the last pulse.
+fn plan_reminder(pattern: ReminderPattern, on: &[SetState], afterwards: &[SetState]) -> Vec<(u32, SetState, bool)> {
+    let mut steps = vec![];
+    let mut slot = 0;
+    for pulse in 0 .. pattern.pulses {
+        let on_states = if pulse == 0 { on } else { &[SetState::Power(true)] };
+        for (index, state) in (0 ..).zip(on_states) {
+            steps.push((slot + index, *state, true));
+        }
+        slot += u32::from(pattern.on_slots).max(u32::try_from(on_states.len()).unwrap_or(u32::MAX));
+        if pulse + 1 < pattern.pulses {
+            steps.push((slot, SetState::Power(false), true));
+            slot += u32::from(pattern.off_slots);
+        }
+    }
+    // the reminder must not stay on because it was late, unless it was never shown
+    for (index, state) in (0 ..).zip(afterwards) {
+        steps.push((slot + index, *state, false));
+    }
+    steps
+}
+
+/// states showing the reminder scene, with the color of `pattern` if it has one
+fn reminder_on_states(pattern: ReminderPattern) -> Vec<SetState> {
+    let mut states = scene::get(scene::REMINDER).map(|scene| scene.states()).unwrap_or_default();
+    if let Some(color) = pattern.color {
+        states.retain(|state| !matches!(state, SetState::Color(_) | SetState::ColorTemperature(_)));
+        states.push(SetState::Color(color));
+    }
+    states
+}
+
+/// number of states appended by `reminder_pattern`. assumes restoring takes three states.
+pub fn reminder_state_amount(pattern: ReminderPattern) -> usize {
+    let afterwards = match pattern.afterwards {
+        ReminderAfterwards::Restore => 3,
+        ReminderAfterwards::Off => 1,
+        ReminderAfterwards::Keep => 0
+    };
+    plan_reminder(pattern, &reminder_on_states(pattern), &[]).len() + afterwards
+}
+
+/// append states for a reminder with the pattern from the config file
+pub fn reminder(govee_queue: &mut GoveeQueue) {
+    reminder_pattern(govee_queue, config::reminder_pattern());
 }
 
-/// append states to show a bright orange with high brightness for about 20 seconds and then turn off again
-pub fn reminder(govee_queue: &mut VecDeque<SetState>) {
-    use constants::{colors::REMINDER as COLOR, brightness::REMINDER as BRIGHTNESS};
+/// append states to show the reminder scene (bright orange with high brightness by default) with `pattern`.
+/// the reminder is left out if it can't be shown in time, and so is what happens afterwards if it wasn't shown at all.
+pub fn reminder_pattern(govee_queue: &mut GoveeQueue, pattern: ReminderPattern) {
+    use constants::{govee::{API_REQUEST_INTERVAL, AVG_SET_STATE_DURATION}, reminder::MAX_DELAY};
     println!("activating reminder...");
-    govee_queue.push_back(SetState::Color(COLOR));
-    govee_queue.push_back(SetState::Brightness(BRIGHTNESS));
-    govee_queue.push_back(SetState::Power(true)); // do nothing
-    govee_queue.push_back(SetState::Power(false));
+
+    let afterwards = match pattern.afterwards {
+        // state after what was queued before the reminder
+        ReminderAfterwards::Restore => govee_queue.expected().state().map_or_else(|| {
+            println!("state of the lamp is not known for reminder, turning off afterwards");
+            vec![SetState::Power(false)]
+        }, |previous| snapshot::restore_states(&previous)),
+        ReminderAfterwards::Off => vec![SetState::Power(false)],
+        ReminderAfterwards::Keep => vec![]
+    };
+
+    let start = timeday::local_now();
+    let slot_duration = chrono::TimeDelta::from_std(API_REQUEST_INTERVAL + AVG_SET_STATE_DURATION).unwrap();
+    let max_delay = chrono::TimeDelta::from_std(MAX_DELAY).unwrap();
+    let group = govee_queue::new_group();
+    for (slot, state, shows) in plan_reminder(pattern, &reminder_on_states(pattern), &afterwards) {
+        let not_before = start + slot_duration * i32::try_from(slot).unwrap_or(i32::MAX);
+        let entry = if shows {
+            Entry { group: Some(Group::Shows(group)), ..Entry::expiring(state, not_before + max_delay) }
+        } else {
+            Entry { group: Some(Group::After(group)), ..Entry::from(state) }
+        };
+        govee_queue.push_back(Entry { not_before: Some(not_before), ..entry });
+    }
 }
 
-/// append states for a sunrise of given duration
-#[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation, clippy::cast_precision_loss)]
-pub fn sunrise(govee_queue: &mut VecDeque<SetState>, sunrise_duration: Duration) {
+/// number of `SetState`s a sunset of given duration generates for brightness and color each
+#[allow(clippy::cast_possible_truncation)]
+pub const fn ramp_state_amount(duration: Duration) -> u32 {
     use constants::govee::{API_REQUEST_INTERVAL, AVG_SET_STATE_DURATION};
-    use constants::sunrise::{govee_brightness, hsv_color};
-
-    // number of `SetState`s to generate for brightness and color each.
-    // f64 type is needed for later calculations.
-    let state_amount = (
-        sunrise_duration.as_millis() /
+    (duration.as_millis() /
         (API_REQUEST_INTERVAL.as_millis() + AVG_SET_STATE_DURATION.as_millis()) /
         2 // for brightness and color each
-    ) as f64;
-
-    let brightness_step = (govee_brightness::STOP - govee_brightness::START) / (state_amount - 1.0);
-    let saturation_step = (hsv_color::saturation::START - hsv_color::saturation::STOP) / (state_amount - 1.0);
-
-    for i in 0 .. state_amount as u32 {
-        let iteration = f64::from(i);
-        govee_queue.push_back(SetState::Brightness(
-            brightness_step.mul_add(iteration, govee_brightness::START)
-                .round() as u8
-        ));
-        govee_queue.push_back(SetState::Color(
+    ) as u32
+}
+
+/// how the color of a sunrise is computed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub enum SunriseColorMode {
+    /// change saturation (and optionally hue) of an orange color
+    #[default]
+    Hsv,
+    /// follow the color of a black body (Planckian locus) from deep red to daylight.
+    /// uses the native color temperature of the lamp where supported.
+    Blackbody
+}
+
+/// how brightness and color change during a sunrise. default is linear with constant hue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SunriseStyle {
+    pub brightness_curve: Curve,
+    /// for saturation and hue, or color temperature
+    pub color_curve: Curve,
+    pub color_mode: SunriseColorMode,
+    /// in degrees, defaults to constant
+    pub hue_start: Option<u16>,
+    /// in degrees, defaults to constant
+    pub hue_stop: Option<u16>
+}
+
+/// color temperature in Kelvin of a blackbody sunrise at `progress` (0 to 1).
+/// interpolates the reciprocal temperature, because its steps are perceived as more even.
+pub fn sunrise_kelvin(curve: Curve, progress: f64) -> f64 {
+    use constants::sunrise::kelvin::{START, STOP};
+    1.0 / curve.interpolate(1.0 / START, 1.0 / STOP, progress)
+}
+
+/// native color temperature if `native` and the lamp model supports `kelvin`, otherwise approximated color
+#[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+fn color_temperature_state(kelvin: f64, native: bool) -> SetState {
+    let kelvin = kelvin.round() as u16;
+    if native && govee_api::color_temperature_range().contains(&kelvin) {
+        SetState::ColorTemperature(kelvin)
+    } else {
+        SetState::Color(kelvin::to_rgb(kelvin))
+    }
+}
+
+/// state of a sunrise or sunset plan, planned relative to its start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SunriseStep {
+    pub offset: Duration,
+    pub state: SetState
+}
+
+/// max number of Govee API calls a sunrise of given duration may use:
+/// one per request interval, but at most its share of the daily rate limit
+pub fn sunrise_budget(duration: Duration) -> usize {
+    use constants::{govee::{API_REQUEST_INTERVAL, AVG_SET_STATE_DURATION}, sunrise::MAX_GOVEE_CALLS};
+    let slots = duration.as_millis() / (API_REQUEST_INTERVAL + AVG_SET_STATE_DURATION).as_millis();
+    usize::try_from(slots).unwrap_or(usize::MAX).min(MAX_GOVEE_CALLS)
+}
+
+/// plan the states of a sunrise of given duration, using at most about `budget` API calls.
+/// the budget is spread evenly over the sunrise, and each slot sets the brightness or color
+/// if its rounded value changed since it was last set. brightness is favoured in the first half,
+/// color in the second half. the final brightness and color are always set.
+/// `native` is whether the lamp supports color temperatures, which changes in finer steps than RGB colors.
+#[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+pub fn plan_sunrise(duration: Duration, style: SunriseStyle, native: bool, budget: usize) -> Vec<SunriseStep> {
+    use constants::sunrise::{govee_brightness, hsv_color};
+
+    let hue_start = style.hue_start.map_or(hsv_color::HUE, f64::from);
+    let hue_stop = style.hue_stop.map_or(hsv_color::HUE, f64::from);
+    let brightness = |progress| SetState::Brightness(
+        style.brightness_curve.interpolate(govee_brightness::START, govee_brightness::STOP, progress).round() as u8
+    );
+    let color = |progress| match style.color_mode {
+        SunriseColorMode::Hsv => SetState::Color(
             hsv::hsv_to_rgb(
-                hsv_color::HUE,
-                saturation_step.mul_add(-iteration, hsv_color::saturation::START),
+                style.color_curve.interpolate(hue_start, hue_stop, progress).rem_euclid(360.0),
+                style.color_curve.interpolate(hsv_color::saturation::START, hsv_color::saturation::STOP, progress),
                 hsv_color::VALUE,
             )
-        ));
+        ),
+        SunriseColorMode::Blackbody => color_temperature_state(sunrise_kelvin(style.color_curve, progress), native)
+    };
+
+    let slots = budget.max(2);
+    let mut steps = vec![];
+    let (mut last_brightness, mut last_color) = (None, None);
+    for slot in 0 .. slots {
+        let progress = slot as f64 / (slots - 1) as f64;
+        // start with the start state even if it is set a bit late
+        let target_brightness = if last_brightness.is_none() { brightness(0.0) } else { brightness(progress) };
+        let target_color = if last_color.is_none() { color(0.0) } else { color(progress) };
+        let brightness_changed = last_brightness != Some(target_brightness);
+        let color_changed = last_color != Some(target_color);
+
+        let state = if brightness_changed && (progress < 0.5 || !color_changed) {
+            last_brightness = Some(target_brightness);
+            target_brightness
+        } else if color_changed {
+            last_color = Some(target_color);
+            target_color
+        } else {
+            continue;
+        };
+        steps.push(SunriseStep { offset: duration.mul_f64(progress), state });
     }
 
-    println!("generated {} sunrise states for {:.1} min sunrise",
-        state_amount as u32,
+    // one of them might not have been set in the last slot
+    for (last, target) in [(last_brightness, brightness(1.0)), (last_color, color(1.0))] {
+        if last != Some(target) {
+            steps.push(SunriseStep { offset: duration, state: target });
+        }
+    }
+    steps
+}
+
+/// number of `SetState`s `sunrise` appends for given duration and style
+pub fn sunrise_state_amount(duration: Duration, style: SunriseStyle) -> usize {
+    plan_sunrise(duration, style, config::native_color_temperature(), sunrise_budget(duration)).len()
+}
+
+/// append planned states for a sunrise of given duration, starting now
+pub fn sunrise(govee_queue: &mut GoveeQueue, sunrise_duration: Duration, style: SunriseStyle) {
+    let start = timeday::local_now();
+    let budget = sunrise_budget(sunrise_duration);
+    let steps = plan_sunrise(sunrise_duration, style, config::native_color_temperature(), budget);
+
+    println!("planned {} sunrise states with a budget of {budget} for {:.1} min sunrise",
+        steps.len(),
         sunrise_duration.as_secs_f32() / 60f32
     );
-}
\ No newline at end of file
+    let sequence = govee_queue.push_sequence(start, steps.into_iter().map(|step| (step.offset, step.state)));
+    SUNRISE_SEQUENCE.store(sequence, Ordering::Relaxed);
+}
+
+/// remove the states of the last sunrise which were not set yet, e.g. to hold its current state.
+/// other queued states are kept. return how many were removed.
+pub fn stop_sunrise(govee_queue: &mut GoveeQueue) -> usize {
+    govee_queue.remove_sequence(SUNRISE_SEQUENCE.load(Ordering::Relaxed))
+}
+
+/// state a sunset starts from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub enum SunsetStart {
+    /// brightness and color of the lamp after the states queued before, daylamp where they are not known
+    Current,
+    /// brightness and color of the daylamp
+    #[default]
+    Daylamp
+}
+
+/// what happens after a sunset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub enum SunsetEnd {
+    #[default]
+    PowerOff,
+    /// leave the nightlamp on
+    Nightlamp
+}
+
+/// brightness and color a sunset of `start` starts from, `expected` being the state after the queued states
+pub fn sunset_start(start: SunsetStart, expected: &Shadow) -> (u8, (u8, u8, u8)) {
+    use constants::{brightness::DAY, colors::DAYLAMP};
+    let (mut brightness, mut rgb_color) = (None, None);
+    if start == SunsetStart::Current {
+        brightness = expected.brightness;
+        rgb_color = match expected.color {
+            Some(SetState::Color(rgb_color)) => Some(rgb_color),
+            Some(SetState::ColorTemperature(kelvin)) => Some(kelvin::to_rgb(kelvin)),
+            _ => None
+        };
+        if brightness.is_none() || rgb_color.is_none() {
+            println!("state of the lamp is not fully known for sunset, starting from daylamp where needed");
+        }
+    }
+    // daylamp scene might not set both
+    let daylamp = scene::get(scene::DAYLAMP);
+    (
+        brightness.or_else(|| daylamp.as_ref().and_then(scene::Scene::brightness)).unwrap_or(DAY),
+        rgb_color.or_else(|| daylamp.as_ref().and_then(scene::Scene::rgb_color)).unwrap_or(DAYLAMP)
+    )
+}
+
+/// number of states appended by `sunset` of given duration
+pub fn sunset_state_amount(duration: Duration, end: SunsetEnd) -> usize {
+    2 * ramp_state_amount(duration) as usize + match end {
+        SunsetEnd::PowerOff => 1,
+        SunsetEnd::Nightlamp => scene::state_amount(scene::NIGHTLAMP)
+    }
+}
+
+/// plan a sunset of given duration from `start` (brightness and color) down to deep orange with brightness for night.
+/// brightness and color alternate, following `curve`.
+#[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+pub fn plan_sunset(duration: Duration, start: (u8, (u8, u8, u8)), curve: Curve) -> Vec<SunriseStep> {
+    use constants::{brightness::NIGHT, colors::SUNSET};
+
+    let state_amount = ramp_state_amount(duration);
+    let (start_brightness, start_color) = start;
+    let interpolate = |from: u8, to: u8, progress: f64| curve.interpolate(f64::from(from), f64::from(to), progress).round() as u8;
+    // one slot per state
+    let slot = duration / (2 * state_amount).max(1);
+
+    (0 .. state_amount).flat_map(|i| {
+        let progress = f64::from(i) / f64::from(state_amount.saturating_sub(1).max(1));
+        let color = (
+            interpolate(start_color.0, SUNSET.0, progress),
+            interpolate(start_color.1, SUNSET.1, progress),
+            interpolate(start_color.2, SUNSET.2, progress)
+        );
+        [
+            SunriseStep { offset: slot * (2 * i), state: SetState::Brightness(interpolate(start_brightness, NIGHT, progress)) },
+            SunriseStep { offset: slot * (2 * i + 1), state: SetState::Color(color) }
+        ]
+    }).collect()
+}
+
+/// append states for a sunset of given duration from `start` (brightness and color)
+/// down to deep orange with brightness for night following `curve`, then power off or activate nightlamp.
+/// the ramp is planned from now, so it catches up instead of stretching when it is delayed.
+pub fn sunset(govee_queue: &mut GoveeQueue, duration: Duration, start: (u8, (u8, u8, u8)), end: SunsetEnd, curve: Curve) {
+    let steps = plan_sunset(duration, start, curve);
+    println!("planned {} sunset states for {:.1} min sunset", steps.len(), duration.as_secs_f32() / 60f32);
+    govee_queue.push_sequence(timeday::local_now(), steps.into_iter().map(|step| (step.offset, step.state)));
+
+    match end {
+        SunsetEnd::PowerOff => govee_queue.push_back(SetState::Power(false)),
+        SunsetEnd::Nightlamp => nightlamp(govee_queue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sunset_ramps_down_to_deep_orange() {
+        use constants::{brightness::NIGHT, colors::{DAYLAMP, SUNSET}};
+        let mut govee_queue = GoveeQueue::default();
+        let duration = Duration::from_secs(10 * 60);
+        sunset(&mut govee_queue, duration, (80, DAYLAMP), SunsetEnd::PowerOff, Curve::Linear);
+        assert_eq!(govee_queue.len(), sunset_state_amount(duration, SunsetEnd::PowerOff));
+
+        let brightnesses = govee_queue.iter()
+            .filter_map(|state| if let SetState::Brightness(b) = state { Some(*b) } else { None })
+            .collect::<Vec<_>>();
+        assert_eq!(brightnesses.first(), Some(&80));
+        assert_eq!(brightnesses.last(), Some(&NIGHT));
+        assert!(brightnesses.windows(2).all(|pair| pair[0] >= pair[1]));
+
+        assert!(matches!(govee_queue.iter().nth(1), Some(SetState::Color(color)) if *color == DAYLAMP));
+        let last_color = govee_queue.iter().rev().find_map(|state| if let SetState::Color(c) = state { Some(*c) } else { None });
+        assert_eq!(last_color, Some(SUNSET));
+        assert!(matches!(govee_queue.iter().next_back(), Some(SetState::Power(false))));
+
+        // planned over the whole duration, the curve keeps the endpoints
+        let steps = plan_sunset(duration, (80, DAYLAMP), Curve::Perceptual);
+        assert_eq!(steps.first().map(|step| (step.offset, step.state)), Some((Duration::ZERO, SetState::Brightness(80))));
+        assert_eq!(steps.last().map(|step| step.state), Some(SetState::Color(SUNSET)));
+        assert!(steps.last().is_some_and(|step| step.offset < duration && step.offset > duration * 9 / 10));
+        let linear = plan_sunset(duration, (80, DAYLAMP), Curve::Linear);
+        assert!(steps[20].state != linear[20].state);
+    }
+
+    #[test]
+    fn sunrise_curves_keep_endpoints() {
+        use constants::sunrise::{govee_brightness, hsv_color};
+        let duration = Duration::from_secs(20 * 60);
+        for curve in [Curve::Linear, Curve::EaseIn, Curve::Perceptual, Curve::Gamma(22)] {
+            let mut govee_queue = GoveeQueue::default();
+            let style = SunriseStyle {
+                brightness_curve: curve, color_curve: curve, color_mode: SunriseColorMode::Hsv, hue_start: Some(10), hue_stop: Some(40)
+            };
+            sunrise(&mut govee_queue, duration, style);
+
+            let brightnesses = govee_queue.iter()
+                .filter_map(|state| if let SetState::Brightness(b) = state { Some(f64::from(*b)) } else { None })
+                .collect::<Vec<_>>();
+            assert_eq!(brightnesses.first(), Some(&govee_brightness::START));
+            assert_eq!(brightnesses.last(), Some(&govee_brightness::STOP));
+            assert!(brightnesses.windows(2).all(|pair| pair[0] <= pair[1]), "{curve:?} brightness is not monotonic");
+
+            let colors = govee_queue.iter()
+                .filter_map(|state| if let SetState::Color(c) = state { Some(*c) } else { None })
+                .collect::<Vec<_>>();
+            assert_eq!(colors.first(), Some(&hsv::hsv_to_rgb(10.0, hsv_color::saturation::START, hsv_color::VALUE)));
+            assert_eq!(colors.last(), Some(&hsv::hsv_to_rgb(40.0, hsv_color::saturation::STOP, hsv_color::VALUE)));
+        }
+    }
+
+    #[test]
+    fn blackbody_sunrise_follows_planckian_locus() {
+        use constants::sunrise::kelvin::{START, STOP};
+        let duration = Duration::from_secs(30 * 60);
+        let style = SunriseStyle { color_mode: SunriseColorMode::Blackbody, ..SunriseStyle::default() };
+        let mut govee_queue = GoveeQueue::default();
+        sunrise(&mut govee_queue, duration, style);
+
+        // below the supported range of the lamp the color is approximated, then native color temperature is used
+        let colors = govee_queue.iter()
+            .filter(|state| !matches!(state, SetState::Brightness(_)))
+            .collect::<Vec<_>>();
+        assert!(matches!(colors.first(), Some(SetState::Color(color)) if *color == kelvin::to_rgb(1800)));
+        assert!(matches!(colors.last(), Some(SetState::ColorTemperature(5000))));
+        let kelvins = colors.iter()
+            .filter_map(|state| if let SetState::ColorTemperature(k) = state { Some(*k) } else { None })
+            .collect::<Vec<_>>();
+        assert!(kelvins.first().is_some_and(|k| *k >= 2000));
+        assert!(kelvins.windows(2).all(|pair| pair[0] <= pair[1]), "color temperature is not increasing");
+
+        // reciprocal interpolation spends more time in warm colors
+        let middle = sunrise_kelvin(Curve::Linear, 0.5);
+        assert!(START < middle && middle < f64::midpoint(START, STOP));
+
+        // without native support every color is approximated and gets less red
+        let rgb = (0 ..= 10)
+            .map(|i| color_temperature_state(sunrise_kelvin(Curve::Linear, f64::from(i) / 10.0), false))
+            .map(|state| if let SetState::Color(color) = state { color } else { panic!("expected color, got {state:?}") })
+            .collect::<Vec<_>>();
+        assert_eq!(rgb.first(), Some(&kelvin::to_rgb(1800)));
+        assert_eq!(rgb.last(), Some(&kelvin::to_rgb(5000)));
+        assert!(rgb.windows(2).all(|pair| pair[0].1 <= pair[1].1 && pair[0].2 <= pair[1].2));
+    }
+
+    #[test]
+    fn sunrise_plan_skips_unchanged_steps() {
+        // one call per request interval
+        let duration = Duration::from_secs(60 * 60);
+        let budget = sunrise_budget(duration);
+        assert_eq!(budget, 553);
+        // long sunrises are limited by the rate limit
+        assert_eq!(sunrise_budget(duration * 4), constants::sunrise::MAX_GOVEE_CALLS);
+        let steps = plan_sunrise(duration, SunriseStyle::default(), true, budget);
+        assert!(steps.len() <= budget + 1);
+        assert!(steps.windows(2).all(|pair| pair[0].offset <= pair[1].offset));
+        assert!(steps.last().is_some_and(|step| step.offset <= duration));
+
+        // only 100 different brightness values exist, so many slots are left out
+        let brightnesses = steps.iter()
+            .filter_map(|step| if let SetState::Brightness(b) = step.state { Some(b) } else { None })
+            .collect::<Vec<_>>();
+        assert_eq!(brightnesses.len(), 100);
+        assert_eq!(brightnesses.last(), Some(&100));
+        assert!(brightnesses.windows(2).all(|pair| pair[0] < pair[1]));
+        for kind in [0, 1] {
+            let states = steps.iter()
+                .map(|step| step.state)
+                .filter(|state| matches!(state, SetState::Brightness(_)) == (kind == 0))
+                .collect::<Vec<_>>();
+            assert!(states.windows(2).all(|pair| pair[0] != pair[1]), "repeated state");
+        }
+
+        // a small budget sets brightness early and color late
+        let steps = plan_sunrise(duration, SunriseStyle::default(), true, 40);
+        let brightness_share = |from: usize, to: usize| steps[from .. to].iter()
+            .filter(|step| matches!(step.state, SetState::Brightness(_)))
+            .count() as f64 / (to - from) as f64;
+        let half = steps.iter().position(|step| step.offset >= duration / 2).unwrap();
+        assert!(brightness_share(0, half) > brightness_share(half, steps.len()));
+    }
+
+    #[test]
+    fn reminder_patterns() {
+        let on = [SetState::Brightness(100), SetState::Color((255, 100, 0))];
+        let off = [SetState::Power(false)];
+
+        // default is like the old reminder, which is on for 3 slots
+        let steps = plan_reminder(ReminderPattern::default(), &on, &off);
+        assert_eq!(steps, [
+            (0, SetState::Brightness(100), true),
+            (1, SetState::Color((255, 100, 0)), true),
+            (3, SetState::Power(false), false)
+        ]);
+
+        let pattern = ReminderPattern { pulses: 3, on_slots: 1, off_slots: 2, ..ReminderPattern::default() };
+        let steps = plan_reminder(pattern, &on, &[]);
+        let slots = steps.iter().map(|(slot, state, _)| (*slot, *state)).collect::<Vec<_>>();
+        assert_eq!(slots, [
+            (0, SetState::Brightness(100)),
+            (1, SetState::Color((255, 100, 0))),
+            (2, SetState::Power(false)),
+            (4, SetState::Power(true)),
+            (5, SetState::Power(false)),
+            (7, SetState::Power(true))
+        ]);
+        assert!(steps.iter().all(|(_, _, expiring)| *expiring));
+
+        assert!(ReminderPattern { pulses: 0, ..ReminderPattern::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn sunset_starts_from_expected_state() {
+        let expected = Shadow::default().apply(SetState::Brightness(70)).apply(SetState::Color((1, 2, 3)));
+        assert_eq!(sunset_start(SunsetStart::Current, &expected), (70, (1, 2, 3)));
+        let daylamp = sunset_start(SunsetStart::Daylamp, &expected);
+        assert_eq!(sunset_start(SunsetStart::Current, &Shadow::default()), daylamp);
+        assert_eq!(sunset_start(SunsetStart::Current, &Shadow::default().apply(SetState::Brightness(70))).0, 70);
+    }
+
+    #[test]
+    fn reminder_restores_queued_state() {
+        let mut govee_queue = GoveeQueue::default();
+        govee_queue.push_back(SetState::Power(true));
+        govee_queue.push_back(SetState::Brightness(40));
+        govee_queue.push_back(SetState::Color((1, 2, 3)));
+        let pattern = ReminderPattern { afterwards: ReminderAfterwards::Restore, ..ReminderPattern::default() };
+        reminder_pattern(&mut govee_queue, pattern);
+        assert_eq!(govee_queue.len(), 3 + reminder_state_amount(pattern));
+        let restored = govee_queue.iter().skip(govee_queue.len() - 3).copied().collect::<Vec<_>>();
+        assert_eq!(restored, [SetState::Power(true), SetState::Brightness(40), SetState::Color((1, 2, 3))]);
+
+        // turns off if the previous state is not known
+        let mut govee_queue = GoveeQueue::default();
+        reminder_pattern(&mut govee_queue, pattern);
+        assert_eq!(govee_queue.iter().last(), Some(&SetState::Power(false)));
+    }
+}
@@ -1,32 +1,476 @@
 use utoipa::ToSchema;
+use itertools::Itertools;
 use tokio::sync::Mutex;
 use serde::{Serialize, Deserialize};
-use std::{time::Duration, sync::Arc};
-use crate::control::state;
-use crate::util::{govee_api::SetState, timeday::TimeDay, fn_queue};
+use std::{time::Duration, sync::{Arc, atomic::{AtomicU32, Ordering}}};
+use chrono::{Days, NaiveDate, NaiveDateTime, TimeDelta};
+use crate::control::{program, scene, snapshot, state};
+use crate::util::{config, cron::Cron, curve::Curve, data_file, govee_api::SetState, solar::SolarTime, timeday::{self, TimeDay}, fn_queue};
 
 pub type SimpleTimers = Arc<Mutex<Vec<SimpleTimer>>>;
 pub type Timers = Arc<Mutex<Vec<Timer>>>;
 
 #[allow(clippy::module_name_repetitions)]
 pub struct SimpleTimer {
-    timeday: TimeDay,
+    trigger: Trigger,
     description: &'static str,
+    /// id of the `Timer` this was generated from
+    parent: u32,
+    /// minutes between an occurrence of the parent `Timer` and this timer
+    shift_min: i32,
+    /// occurrences of the parent `Timer` to leave out
+    suppression: Suppression,
+    /// estimated minutes until the states queued by `function` are set
+    drain_min: i32,
+    /// policy of the parent `Timer`
+    conflict: ConflictPolicy,
     /// take `govee_queue` as argument
     function: fn_queue::Element
 }
 
+/// when a `SimpleTimer` matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// every week on the given days and time
+    Weekly(TimeDay),
+    /// once on the given date and time (with seconds set to 0)
+    Once(NaiveDateTime),
+    /// whenever `cron` matched `shift_min` minutes ago
+    Cron { cron: Cron, shift_min: i32 }
+}
+impl Trigger {
+    fn matches(&self, datetime: &NaiveDateTime) -> bool {
+        match self {
+            Self::Weekly(timeday) => timeday.matches(datetime),
+            Self::Once(once) => *once == timeday::truncate_to_minute(datetime),
+            Self::Cron { cron, shift_min } => cron.matches(&(*datetime - TimeDelta::minutes((*shift_min).into())))
+        }
+    }
+}
+// format like 15:20@["Mo", "Tu"], 2024-06-21 21:18 or cron(0 7 * * MON)-20min
+impl std::fmt::Display for Trigger {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Weekly(timeday) => timeday.fmt(f),
+            Self::Once(once) => f.write_fmt(format_args!("{}", once.format("%Y-%m-%d %H:%M"))),
+            Self::Cron { cron, shift_min } => f.write_fmt(format_args!("cron({cron}){shift_min:+}min"))
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq, Hash)]
 pub struct Timer {
+    /// assigned by the server, unique among all timers
+    #[serde(default)]
+    #[schema(read_only)]
+    id: u32,
+    /// defaults to "timer <id>"
+    #[serde(default)]
+    name: String,
     enable: bool,
+    /// time of the timer every week. exactly one of `timeday`, `solar` and `cron` has to be given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(inline)]
+    timeday: Option<TimeDay>,
+    /// time of the timer relative to the sun at the location from the config file.
+    /// recomputed every day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     #[schema(inline)]
-    timeday: TimeDay,
+    solar: Option<SolarTime>,
+    /// cron-style schedule of the timer like "0 9-17/2 * * MON-FRI" or "30 7 * * MON#1".
+    /// fields are minute, hour, day of month, month and day of week (0 or 7 is sunday),
+    /// optionally preceded by seconds which are ignored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "0 9-17/2 * * MON-FRI")]
+    cron: Option<String>,
     #[schema(inline)]
-    action: TimerAction
+    action: TimerAction,
+    /// date of a single occurrence to skip, e.g. to leave out tomorrow's alarm
+    #[serde(default)]
+    skipped_date: Option<NaiveDate>,
+    /// skip all occurrences before this date
+    #[serde(default)]
+    paused_until: Option<NaiveDate>,
+    /// names of calendars from the config file. skip occurrences on dates in any of them.
+    #[serde(default)]
+    suppress_on: Vec<String>,
+    /// names of calendars from the config file. if not empty, skip occurrences on dates in none of them.
+    #[serde(default)]
+    only_on: Vec<String>,
+    /// what to do if this timer matches while the action of another timer is still running
+    #[serde(default)]
+    conflict: ConflictPolicy
 }
 impl Timer {
-    pub const fn get_timeday(&self) -> &TimeDay { &self.timeday }
+    pub const fn get_id(&self) -> u32 { self.id }
+    pub const fn is_enabled(&self) -> bool { self.enable }
+    pub const fn get_timeday(&self) -> Option<&TimeDay> { self.timeday.as_ref() }
+    pub const fn get_solar(&self) -> Option<&SolarTime> { self.solar.as_ref() }
+    pub const fn get_cron(&self) -> Option<&String> { self.cron.as_ref() }
     pub const fn get_action(&self) -> &TimerAction { &self.action }
+    /// names of all calendars this timer references
+    pub fn get_calendars(&self) -> impl Iterator<Item = &String> {
+        self.suppress_on.iter().chain(self.only_on.iter())
+    }
+
+    pub const fn set_id(&mut self, id: u32) { self.id = id; }
+    pub const fn set_enable(&mut self, enable: bool) { self.enable = enable; }
+    pub const fn set_skipped_date(&mut self, date: Option<NaiveDate>) { self.skipped_date = date; }
+    pub const fn set_paused_until(&mut self, date: Option<NaiveDate>) { self.paused_until = date; }
+
+    fn suppression(&self) -> Suppression {
+        Suppression {
+            skipped_date: self.skipped_date,
+            paused_until: self.paused_until,
+            suppress_on: self.suppress_on.clone(),
+            only_on: self.only_on.clone()
+        }
+    }
+
+    /// reason why the occurrence on `date` is left out, if it is
+    pub fn suppression_reason(&self, date: NaiveDate) -> Option<String> {
+        self.suppression().reason(date)
+    }
+
+    /// earliest date/time strictly after `after` matched by `timeday`, `solar` or `cron`.
+    /// none for solar times without location or event in the next days
+    /// and for invalid cron schedules or ones without match in the next years.
+    pub fn next_match_after(&self, after: &NaiveDateTime) -> Option<NaiveDateTime> {
+        match (&self.timeday, &self.solar, &self.cron) {
+            (Some(timeday), _, _) => Some(timeday.next_after(after)),
+            (None, Some(solar), _) => solar.next_after(after, config::location()?),
+            (None, None, Some(cron)) => Cron::parse(cron).ok()?.next_after(after),
+            (None, None, None) => None
+        }
+    }
+
+    /// date/time of the next occurrence that has not started yet,
+    /// which is the earliest generated `SimpleTimer` of that occurrence not being in the past.
+    pub fn next_occurrence(&self, after: &NaiveDateTime) -> Option<NaiveDateTime> {
+        let earliest_shift = action_parts(&self.action).iter()
+            .map(|part| part.shift_min)
+            .min().unwrap_or(0);
+        self.next_match_after(&(*after - TimeDelta::minutes(earliest_shift.into())))
+    }
+
+    /// events (like generated `SimpleTimer`s matching) from `from` to `to` (both inclusive),
+    /// sorted by date and time. ignores `enable`.
+    pub fn events(&self, from: &NaiveDateTime, to: &NaiveDateTime) -> Vec<TimerEvent> {
+        let parts = action_parts(&self.action);
+        let min_shift = TimeDelta::minutes(parts.iter().map(|part| part.shift_min).min().unwrap_or(0).into());
+        let max_shift = TimeDelta::minutes(parts.iter().map(|part| part.shift_min).max().unwrap_or(0).into());
+
+        // occurrences with events in the given range
+        let occurrences = std::iter::successors(
+            self.next_match_after(&(*from - max_shift - TimeDelta::minutes(1))),
+            |datetime| self.next_match_after(datetime)
+        ).take_while(|datetime| *datetime + min_shift <= *to);
+
+        occurrences
+            .flat_map(|occurrence| {
+                let suppressed = self.suppression_reason(occurrence.date());
+                parts.iter()
+                    .map(|part| TimerEvent {
+                        datetime: occurrence + TimeDelta::minutes(part.shift_min.into()),
+                        occurrence,
+                        description: part.description,
+                        govee_calls: part.govee_calls,
+                        drain_min: part.drain_min(),
+                        suppressed: suppressed.clone()
+                    })
+                    .filter(|event| *from <= event.datetime && event.datetime <= *to)
+                    .collect_vec()
+            })
+            .sorted_by_key(|event| event.datetime)
+            .collect()
+    }
+}
+
+/// event of a single `Timer`, see `Timer::events`
+#[derive(Debug, Clone)]
+pub struct TimerEvent {
+    pub datetime: NaiveDateTime,
+    /// date/time matched by the `Timer` this event is part of
+    pub occurrence: NaiveDateTime,
+    /// like "sunrise" or "nightlamp on"
+    pub description: &'static str,
+    /// how many states the event queues to be set with the Govee API
+    pub govee_calls: usize,
+    /// estimated minutes until all states of the event are set
+    pub drain_min: i32,
+    /// reason why the event will be left out, if it will
+    pub suppressed: Option<String>
+}
+
+/// occurrences of a `Timer` to leave out.
+/// an occurrence is identified by the date its `timeday`, `solar` or `cron` matches.
+#[derive(Debug, Clone, Default)]
+struct Suppression {
+    skipped_date: Option<NaiveDate>,
+    paused_until: Option<NaiveDate>,
+    suppress_on: Vec<String>,
+    only_on: Vec<String>
+}
+impl Suppression {
+    fn applies_to(&self, date: NaiveDate) -> bool {
+        self.reason(date).is_some()
+    }
+
+    /// reason why the occurrence on `date` is left out, if it is.
+    /// calendars that are not defined contain no dates.
+    fn reason(&self, date: NaiveDate) -> Option<String> {
+        let in_calendar = |name: &String| config::calendar(name).is_some_and(|c| c.contains(date));
+
+        if self.skipped_date == Some(date) {
+            return Some(String::from("skipped"));
+        }
+        if let Some(until) = self.paused_until.filter(|&until| date < until) {
+            return Some(format!("paused until {until}"));
+        }
+        if let Some(name) = self.suppress_on.iter().find(|&name| in_calendar(name)) {
+            return Some(format!("suppressed by calendar {name}"));
+        }
+        if !self.only_on.is_empty() && !self.only_on.iter().any(in_calendar) {
+            return Some(format!("not in calendar(s) {}", self.only_on.join(", ")));
+        }
+        None
+    }
+}
+
+/// how to handle a `Timer` matching while the states of another timer are still being set
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema, PartialEq, Eq, Hash)]
+pub enum ConflictPolicy {
+    /// leave out this occurrence
+    Reject,
+    /// stop the other timer by clearing the Govee queue and leave out the rest of its occurrence
+    Preempt,
+    /// delay this occurrence until the other timer is done
+    QueueAfter,
+    /// queue states regardless of other timers
+    #[default]
+    Ignore
+}
+
+/// runtime state to enforce `ConflictPolicy`s, kept between calls of `check_timers`
+#[derive(Default)]
+pub struct Conflicts {
+    /// estimated windows in which states queued by matched `SimpleTimer`s are being set
+    windows: Vec<ActionWindow>,
+    /// occurrences of which the remaining parts are left out or delayed
+    handled: Vec<HandledOccurrence>,
+    /// parts of occurrences to queue later
+    delayed: Vec<DelayedPart>
+}
+
+struct ActionWindow {
+    /// id of the `Timer`
+    timer: u32,
+    /// date/time matched by the `Timer`
+    occurrence: NaiveDateTime,
+    until: NaiveDateTime
+}
+
+struct HandledOccurrence {
+    /// id of the `Timer`
+    timer: u32,
+    /// date/time matched by the `Timer`
+    occurrence: NaiveDateTime,
+    handling: Handling
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Handling {
+    Rejected,
+    Preempted,
+    Delayed(TimeDelta)
+}
+
+struct DelayedPart {
+    at: NaiveDateTime,
+    window: ActionWindow,
+    description: &'static str,
+    function: fn_queue::Element
+}
+
+/// what to do with a matching `SimpleTimer`
+#[derive(Debug, PartialEq, Eq)]
+enum Decision {
+    Run,
+    /// clear the Govee queue, then run
+    Preempt,
+    /// has been delayed until the given date/time
+    Delay(NaiveDateTime),
+    /// leave out for the given reason
+    Skip(String)
+}
+
+impl Conflicts {
+    /// decide what to do with `timer` matching at `now` (with seconds set to 0)
+    /// and remember the resulting windows, delays and left out occurrences
+    fn decide(&mut self, timer: &SimpleTimer, now: NaiveDateTime) -> Decision {
+        let window = ActionWindow {
+            timer: timer.parent,
+            occurrence: now - TimeDelta::minutes(timer.shift_min.into()),
+            until: now + TimeDelta::minutes(timer.drain_min.into())
+        };
+
+        // previously handled part of the same occurrence
+        let handling = self.handled.iter()
+            .find(|handled| handled.timer == window.timer && handled.occurrence == window.occurrence)
+            .map(|handled| handled.handling);
+        match handling {
+            Some(Handling::Rejected) => return Decision::Skip(String::from("rejected because of a conflict")),
+            Some(Handling::Preempted) => return Decision::Skip(String::from("preempted by another timer")),
+            Some(Handling::Delayed(delay)) => return self.delay(timer, window, now, now + delay),
+            None => ()
+        }
+
+        let conflicting = self.windows.iter()
+            .filter(|other| other.timer != window.timer && other.until > now)
+            .map(|other| (other.timer, other.occurrence, other.until))
+            .collect_vec();
+        if conflicting.is_empty() || timer.conflict == ConflictPolicy::Ignore {
+            self.windows.push(window);
+            return Decision::Run;
+        }
+
+        let other_timers = conflicting.iter().map(|(timer, _, _)| timer).unique().join(", ");
+        match timer.conflict {
+            ConflictPolicy::Reject => {
+                self.handle(&window, Handling::Rejected);
+                Decision::Skip(format!("rejected because of running timer(s) {other_timers}"))
+            },
+            ConflictPolicy::Preempt => {
+                for (other_timer, other_occurrence, _) in conflicting {
+                    let other = ActionWindow { timer: other_timer, occurrence: other_occurrence, until: now };
+                    self.handle(&other, Handling::Preempted);
+                    self.windows.retain(|w| w.timer != other_timer || w.occurrence != other_occurrence);
+                    self.delayed.retain(|d| d.window.timer != other_timer || d.window.occurrence != other_occurrence);
+                }
+                self.windows.push(window);
+                Decision::Preempt
+            },
+            ConflictPolicy::QueueAfter => {
+                let at = conflicting.iter().map(|(_, _, until)| *until).max().unwrap();
+                self.handle(&window, Handling::Delayed(at - now));
+                self.delay(timer, window, now, at)
+            },
+            ConflictPolicy::Ignore => unreachable!()
+        }
+    }
+
+    fn handle(&mut self, window: &ActionWindow, handling: Handling) {
+        self.handled.push(HandledOccurrence { timer: window.timer, occurrence: window.occurrence, handling });
+    }
+
+    /// queue part of `timer` matched at `now` at `at` instead
+    fn delay(&mut self, timer: &SimpleTimer, window: ActionWindow, now: NaiveDateTime, at: NaiveDateTime) -> Decision {
+        let window = ActionWindow { until: window.until + (at - now), ..window };
+        self.delayed.push(DelayedPart {
+            at,
+            window,
+            description: timer.description,
+            function: Arc::clone(&timer.function)
+        });
+        Decision::Delay(at)
+    }
+
+    /// take delayed parts that are due at `now` and forget what is not needed anymore
+    fn take_due(&mut self, now: NaiveDateTime) -> Vec<DelayedPart> {
+        let (due, delayed) = std::mem::take(&mut self.delayed).into_iter()
+            .partition::<Vec<_>, _>(|part| part.at <= now);
+        self.delayed = delayed;
+        self.windows.retain(|window| window.until > now);
+        // occurrences don't last longer than this
+        self.handled.retain(|handled| handled.occurrence + TimeDelta::days(30) > now);
+        due
+    }
+}
+
+/// estimated minutes to set `govee_calls` states with the Govee API, at least 1
+#[allow(clippy::cast_possible_truncation)]
+fn drain_min(govee_calls: usize) -> i32 {
+    use crate::constants::govee::{API_REQUEST_INTERVAL, AVG_SET_STATE_DURATION};
+    let millis = govee_calls as u128 * (API_REQUEST_INTERVAL + AVG_SET_STATE_DURATION).as_millis();
+    millis.div_ceil(60 * 1000).max(1) as i32
+}
+
+/// overlapping windows in which states of events of different enabled `timers` are being set,
+/// from `from` to `to`, as messages. only the first overlap of each pair of timers is included.
+pub fn overlaps(timers: &[Timer], from: &NaiveDateTime, to: &NaiveDateTime) -> Vec<String> {
+    let windows = timers.iter()
+        .filter(|timer| timer.enable)
+        .flat_map(|timer| timer.events(from, to).into_iter()
+            .filter(|event| event.suppressed.is_none())
+            .map(move |event| (timer, event.description, event.datetime,
+                event.datetime + TimeDelta::minutes(event.drain_min.into()))))
+        .sorted_by_key(|(_, _, start, _)| *start)
+        .collect_vec();
+
+    windows.iter()
+        .tuple_combinations()
+        .filter(|((a, _, _, a_stop), (b, _, b_start, _))| a.id != b.id && b_start < a_stop)
+        .unique_by(|((a, ..), (b, ..))| (a.id.min(b.id), a.id.max(b.id)))
+        .map(|((a, a_description, _, _), (b, b_description, b_start, _))| format!(
+            "{b_description:?} of timer {} at {} overlaps {a_description:?} of timer {}, handling with policy {:?}",
+            b.id, b_start.format("%Y-%m-%d %H:%M"), a.id, b.conflict
+        ))
+        .collect()
+}
+
+/// occurrence of a `Timer` that will be left out
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SuppressedOccurrence {
+    /// id of the timer
+    timer: u32,
+    /// date and time matched by the timer's `timeday`, `solar` or `cron`
+    datetime: NaiveDateTime,
+    reason: String
+}
+
+/// occurrences of enabled `timers` from `from` (exclusive) to `to` (inclusive) that will be left out,
+/// from the events of `Timer::events`
+pub fn suppressed_occurrences(timers: &[Timer], from: &NaiveDateTime, to: &NaiveDateTime) -> Vec<SuppressedOccurrence> {
+    timers.iter()
+        .filter(|timer| timer.enable)
+        .flat_map(|timer| timer.events(from, to).into_iter()
+            .filter(|event| *from < event.occurrence && event.occurrence <= *to)
+            .filter_map(|event| event.suppressed
+                .map(|reason| SuppressedOccurrence { timer: timer.id, datetime: event.occurrence, reason }))
+            // one per occurrence instead of per part
+            .unique_by(|occurrence| occurrence.datetime))
+        .sorted_by_key(|occurrence| occurrence.datetime)
+        .collect()
+}
+
+/// concrete upcoming event of a `Timer`, like a generated `SimpleTimer` matching
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ScheduledEvent {
+    datetime: NaiveDateTime,
+    /// id of the timer
+    timer: u32,
+    /// name of the timer
+    timer_name: String,
+    /// like "sunrise" or "nightlamp on"
+    description: String,
+    /// reason why the event will be left out, if it will
+    suppressed: Option<String>
+}
+
+/// events of enabled `timers` from `from` to `to` (both inclusive), sorted by date and time
+pub fn schedule(timers: &[Timer], from: &NaiveDateTime, to: &NaiveDateTime) -> Vec<ScheduledEvent> {
+    timers.iter()
+        .filter(|timer| timer.enable)
+        .flat_map(|timer| timer.events(from, to).into_iter()
+            .map(|event| ScheduledEvent {
+                datetime: event.datetime,
+                timer: timer.id,
+                timer_name: timer.name.clone(),
+                description: event.description.to_owned(),
+                suppressed: event.suppressed
+            }))
+        .sorted_by_key(|event| event.datetime)
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq, Hash)]
@@ -35,7 +479,8 @@ impl Timer {
 #[allow(clippy::module_name_repetitions)]
 pub enum TimerAction {
     /// alarm for waking up with sunrise.
-    /// sunrise finishes on `timeday`, stays on for `stay_on_for_min`, activates daylamp and turns off.
+    /// sunrise finishes on the time of the timer, stays on for `stay_on_for_min`, activates daylamp and turns off,
+    /// or restores the state from before the sunrise.
     /// nightlamp will be on for `nightlamp_min`,
     /// with `sleep_min` between the nightlamp turning off and the sunrise finishing.
     Sunrise {
@@ -52,7 +497,40 @@ pub enum TimerAction {
         /// how long the nightlamp should stay on.
         /// use 0 to disable.
         #[schema(minimum = 0, maximum = 32767)] // i16::MAX
-        nightlamp_min: u16
+        nightlamp_min: u16,
+        #[serde(default)]
+        brightness_curve: Curve,
+        /// for saturation and hue, or color temperature
+        #[serde(default)]
+        color_curve: Curve,
+        #[serde(default)]
+        color_mode: state::SunriseColorMode,
+        /// hue in degrees at the start. defaults to the constant sunrise hue.
+        #[serde(default)]
+        #[schema(minimum = 0, maximum = 360)]
+        hue_start: Option<u16>,
+        /// hue in degrees at the end. defaults to the constant sunrise hue.
+        #[serde(default)]
+        #[schema(minimum = 0, maximum = 360)]
+        hue_stop: Option<u16>,
+        /// restore the state from before the sunrise instead of activating daylamp and turning off
+        #[serde(default)]
+        restore_afterwards: bool
+    },
+    /// wind down for falling asleep.
+    /// sunset starts on the time of the timer and ramps from `start` down to deep orange with brightness for night,
+    /// then powers off or leaves the nightlamp on.
+    Sunset {
+        /// how long the sunset should be
+        #[schema(minimum = 1, maximum = 32767)] // i16::MAX
+        duration_min: u16,
+        #[serde(default)]
+        start: state::SunsetStart,
+        #[serde(default)]
+        end: state::SunsetEnd,
+        /// how brightness and color change
+        #[serde(default)]
+        curve: Curve
     },
     /// set bright orange color with high brightness to be active for about 20 seconds
     Reminder,
@@ -76,145 +554,72 @@ pub enum TimerAction {
         #[schema(minimum = 0, maximum = 255)]
         b: u8
     },
+    /// set white light with given color temperature in Kelvin.
+    /// the supported range depends on the lamp model.
+    ColorTemperatureState {
+        #[schema(minimum = 1000, maximum = 10000)]
+        kelvin: u16
+    },
+    /// run the stored light program with given name, starting on the time of the timer
+    Program { name: String },
+    /// activate the user-defined or built-in scene with given name
+    Scene { name: String },
 }
 
 /// attempt to load timers from [`crate::constants::DATA_FILE_NAME`]
 /// and process them into simple timers.
 /// return new empty timers when running into problems.
 pub async fn load_timers(simple_timers: &SimpleTimers) -> Timers {
-    let path = dirs_next::data_dir();
-    if path.is_none() {
-        println!("SETUP: couldn't get path to data dir for timer file, using empty timers...");
-        return Arc::new(Mutex::new(vec![]));
-    }
-    let mut path = path.unwrap();
-    path.push(crate::constants::DATA_FILE_NAME);
-
-    let content = std::fs::read_to_string(path);
-    if content.is_err() {
-        println!("SETUP: timer file doesn't exist, using empty timers...");
-        return Arc::new(Mutex::new(vec![]));
-    }
+    let mut timers = match data_file::read::<Vec<Timer>>(crate::constants::DATA_FILE_NAME) {
+        Ok(timers) => timers,
+        Err(message) => {
+            println!("SETUP: {message} for timers, using empty timers...");
+            return Arc::new(Mutex::new(vec![]));
+        }
+    };
+    // timers from older versions have no ids
+    assign_ids(&mut timers);
 
-    let timers = serde_json::from_str::<Vec<Timer>>(&content.unwrap());
-    if timers.is_err() {
-        println!("SETUP: couldn't parse existing timer file, using empty timers...");
-        return Arc::new(Mutex::new(vec![]));
-    }
-    let timers = timers.unwrap();
-    
     println!("SETUP: successfully loaded {} timer(s) from file", timers.len());
     let timers = Arc::new(Mutex::new(timers));
     process_timers(&timers, simple_timers).await;
     timers
 }
 
+/// give every timer without id (0) or with an id already used by a previous timer
+/// a new unique id. also name timers without name.
+pub fn assign_ids(timers: &mut [Timer]) {
+    let mut next_id = timers.iter()
+        .map(|timer| timer.id)
+        .max().unwrap_or(0) + 1;
+    let mut used_ids = vec![];
+
+    for timer in timers {
+        if timer.id == 0 || used_ids.contains(&timer.id) {
+            timer.id = next_id;
+            next_id += 1;
+        }
+        used_ids.push(timer.id);
+        if timer.name.is_empty() {
+            timer.name = format!("timer {}", timer.id);
+        }
+    }
+}
+
 /// convert `Timer`s to `SimpleTimer`s and save them to `simple_timers`.
-#[allow(clippy::too_many_lines)]
+/// has to be called every day to recompute timers depending on the date, like solar times.
 pub async fn process_timers(timers: &Timers, simple_timers: &SimpleTimers) {
-    let mut generated_timers: Vec<SimpleTimer> = vec![];
-
+    let now = timeday::local_now();
+    let today = now.date();
     let timers = timers.lock().await;
-    for timer in timers.iter() {
-        // skip disabled timers
-        if !timer.enable { continue; }
-        match timer.action {
-            TimerAction::Sunrise { duration_min, stay_on_for_min, sleep_min, nightlamp_min } => {
-                if nightlamp_min > 0 {
-                    generated_timers.push(SimpleTimer {
-                        description: "nightlamp on",
-                        #[allow(clippy::cast_possible_wrap)]
-                        timeday: timer.timeday.shift_time(
-                            0,
-                            - (sleep_min as i16) - (nightlamp_min as i16)
-                        ),
-                        function: Arc::new(state::nightlamp)
-                    });
-                    generated_timers.push(SimpleTimer {
-                        description: "nightlamp off",
-                        #[allow(clippy::cast_possible_wrap)]
-                        timeday: timer.timeday.shift_time(
-                            0,
-                            - (sleep_min as i16)
-                        ),
-                        function: Arc::new(|govee_queue|
-                            govee_queue.push_back(SetState::Power(false)))
-                    });
-                }
-                generated_timers.push(SimpleTimer {
-                    description: "sunrise",
-                    #[allow(clippy::cast_possible_wrap)]
-                    timeday: timer.timeday.shift_time(
-                        0,
-                        - (duration_min as i16)
-                    ),
-                    function: Arc::new(move |govee_queue| {
-                        state::sunrise(
-                            govee_queue,
-                            Duration::from_secs(u64::from(duration_min) * 60)
-                        );
-                    })
-                });
-                generated_timers.push(SimpleTimer {
-                    description: "daylamp => turn off",
-                    #[allow(clippy::cast_possible_wrap)]
-                    timeday: timer.timeday.shift_time(
-                        0,
-                        stay_on_for_min as i16
-                    ),
-                    function: Arc::new(|govee_queue| {
-                        state::daylamp(govee_queue);
-                        govee_queue.push_back(SetState::Power(false));
-                    })
-                });
-            },
-            TimerAction::Reminder => {
-                generated_timers.push(SimpleTimer {
-                    description: "reminder",
-                    timeday: timer.timeday.clone(),
-                    function: Arc::new(state::reminder)
-                });
-            },
-            TimerAction::Nightlamp => {
-                generated_timers.push(SimpleTimer {
-                    description: "nightlamp on",
-                    timeday: timer.timeday.clone(),
-                    function: Arc::new(state::nightlamp)
-                });
-            },
-            TimerAction::Daylamp => {
-                generated_timers.push(SimpleTimer {
-                    description: "daylamp on",
-                    timeday: timer.timeday.clone(),
-                    function: Arc::new(state::daylamp)
-                });
-            },
-            TimerAction::PowerState { power } => {
-                generated_timers.push(SimpleTimer {
-                    description: "set power",
-                    timeday: timer.timeday.clone(),
-                    function: Arc::new(move |govee_queue|
-                        govee_queue.push_back(SetState::Power(power)))
-                });
-            },
-            TimerAction::BrightnessState { brightness } => {
-                generated_timers.push(SimpleTimer {
-                    description: "set brightness",
-                    timeday: timer.timeday.clone(),
-                    function: Arc::new(move |govee_queue|
-                        govee_queue.push_back(SetState::Brightness(brightness)))
-                });
-            },
-            TimerAction::ColorState { r, g, b } => {
-                generated_timers.push(SimpleTimer {
-                    description: "set color",
-                    timeday: timer.timeday.clone(),
-                    function: Arc::new(move |govee_queue|
-                        govee_queue.push_back(SetState::Color((r, g, b))))
-                });
-            },
-        }
+    for overlap in overlaps(&timers, &now, &(now + TimeDelta::days(2))) {
+        println!("{overlap}");
     }
+    let generated_timers = timers.iter()
+        // skip disabled timers
+        .filter(|timer| timer.enable)
+        .flat_map(|timer| generate_simple_timers(timer, today))
+        .collect::<Vec<_>>();
 
     println!("updated timers with {} generated simple timer(s) from {} complex timer(s)", generated_timers.len(), timers.len());
     // free lock when not needed anymore
@@ -222,49 +627,322 @@ pub async fn process_timers(timers: &Timers, simple_timers: &SimpleTimers) {
 
     if !generated_timers.is_empty() {
         for timer in &generated_timers {
-            println!("{}: {}", timer.timeday, timer.description);
+            println!("{}: {}", timer.trigger, timer.description);
         }
     }
 
     *simple_timers.lock().await = generated_timers;
 }
 
+/// convert a single `Timer` to `SimpleTimer`s.
+/// for solar times, only generate timers for today and tomorrow.
+fn generate_simple_timers(timer: &Timer, today: NaiveDate) -> Vec<SimpleTimer> {
+    let parts = action_parts(&timer.action);
+    let simple_timer = |part: &ActionPart, trigger| SimpleTimer {
+        trigger,
+        description: part.description,
+        parent: timer.id,
+        shift_min: part.shift_min,
+        suppression: timer.suppression(),
+        drain_min: part.drain_min(),
+        conflict: timer.conflict,
+        function: Arc::clone(&part.function)
+    };
+
+    if let Some(timeday) = &timer.timeday {
+        return parts.iter().filter_map(|part| {
+            let Ok(shift_min) = i16::try_from(part.shift_min) else {
+                println!("{} of timer {} is shifted by {} minutes, which is too far, ignoring part", part.description, timer.id, part.shift_min);
+                return None;
+            };
+            Some(simple_timer(part, Trigger::Weekly(timeday.shift_time(0, shift_min))))
+        }).collect();
+    }
+
+    if let Some(cron) = &timer.cron {
+        let Ok(cron) = Cron::parse(cron) else {
+            println!("cron schedule {cron:?} of timer {} could not be parsed, ignoring timer", timer.id);
+            return vec![];
+        };
+        return parts.iter().map(|part| simple_timer(
+            part,
+            Trigger::Cron { cron: cron.clone(), shift_min: part.shift_min }
+        )).collect();
+    }
+
+    let (Some(solar), Some(location)) = (&timer.solar, config::location()) else {
+        return vec![];
+    };
+    // dates of occurrences which might have generated timers today or tomorrow
+    let min_shift = parts.iter().map(|part| part.shift_min).min().unwrap_or(0);
+    let max_shift = parts.iter().map(|part| part.shift_min).max().unwrap_or(0);
+    let first_date = today - Days::new((max_shift.max(0) / (60 * 24) + 1).try_into().unwrap());
+    let last_date = today + Days::new(((-min_shift).max(0) / (60 * 24) + 2).try_into().unwrap());
+
+    first_date.iter_days()
+        .take_while(|date| *date <= last_date)
+        .filter_map(|date| solar.on(date, location))
+        .flat_map(|datetime| parts.iter().map(move |part|
+            (part, datetime + TimeDelta::minutes(part.shift_min.into()))))
+        .filter(|(_, datetime)| datetime.date() >= today && datetime.date() <= today + Days::new(1))
+        .map(|(part, datetime)| simple_timer(part, Trigger::Once(datetime)))
+        .collect()
+}
+
+/// part of a `TimerAction` that happens relative to the time of its `Timer`
+struct ActionPart {
+    description: &'static str,
+    /// minutes between the time of the `Timer` and this part
+    shift_min: i32,
+    /// how many states `function` queues
+    govee_calls: usize,
+    /// minutes over which the queued states are planned, 0 if they are set as fast as possible
+    planned_min: i32,
+    /// take `govee_queue` as argument
+    function: fn_queue::Element
+}
+
+impl ActionPart {
+    /// estimated minutes until all states of this part are set
+    fn drain_min(&self) -> i32 {
+        drain_min(self.govee_calls).max(self.planned_min)
+    }
+}
+
+/// minutes after the first part, description and function of each part of `action`, in order.
+/// for running an action once instead of relative to the time of a `Timer`.
+pub fn action_steps(action: &TimerAction) -> Vec<(i32, &'static str, fn_queue::Element)> {
+    let parts = action_parts(action);
+    let first = parts.iter().map(|part| part.shift_min).min().unwrap_or(0);
+    parts.into_iter()
+        .map(|part| (part.shift_min - first, part.description, part.function))
+        .sorted_by_key(|(offset_min, _, _)| *offset_min)
+        .collect()
+}
+
+/// split `action` into parts that happen at different times
+#[allow(clippy::too_many_lines)]
+fn action_parts(action: &TimerAction) -> Vec<ActionPart> {
+    let mut parts: Vec<ActionPart> = vec![];
+
+    // index of a part with planned states and the minutes they are planned over
+    let mut planned = None;
+    // shorthand to add a part, returning its index
+    let mut part = |description, shift_min: i32, govee_calls: usize, function: fn_queue::Element| {
+        parts.push(ActionPart { description, shift_min, govee_calls, planned_min: 0, function });
+        parts.len() - 1
+    };
+
+    match *action {
+        TimerAction::Sunrise {
+            duration_min, stay_on_for_min, sleep_min, nightlamp_min,
+            brightness_curve, color_curve, color_mode, hue_start, hue_stop, restore_afterwards
+        } => {
+            let style = state::SunriseStyle { brightness_curve, color_curve, color_mode, hue_start, hue_stop };
+            if nightlamp_min > 0 {
+                part(
+                    "nightlamp on",
+                    - i32::from(sleep_min) - i32::from(nightlamp_min),
+                    scene::state_amount(scene::NIGHTLAMP),
+                    Arc::new(state::nightlamp)
+                );
+                part(
+                    "nightlamp off",
+                    - i32::from(sleep_min),
+                    1,
+                    Arc::new(|govee_queue|
+                        govee_queue.push_back(SetState::Power(false)))
+                );
+            }
+            let sunrise_duration = Duration::from_secs(u64::from(duration_min) * 60);
+            // id of the snapshot taken before the sunrise, shared by its parts
+            let snapshot_id = Arc::new(AtomicU32::new(0));
+            let taken_id = Arc::clone(&snapshot_id);
+            let sunrise = part(
+                "sunrise",
+                - i32::from(duration_min),
+                state::sunrise_state_amount(sunrise_duration, style),
+                Arc::new(move |govee_queue| {
+                    if restore_afterwards {
+                        // includes states queued right before, like turning off the nightlamp
+                        let id = snapshot::take_expected(govee_queue).map_or(0, |snapshot| snapshot.id);
+                        taken_id.store(id, Ordering::Relaxed);
+                    }
+                    state::sunrise(govee_queue, sunrise_duration, style);
+                })
+            );
+            planned = Some((sunrise, i32::from(duration_min)));
+            if restore_afterwards {
+                part(
+                    "restore previous state",
+                    i32::from(stay_on_for_min),
+                    (scene::state_amount(scene::DAYLAMP) + 1).max(3),
+                    Arc::new(move |govee_queue| {
+                        // e.g. server restarted during the sunrise or the state was not known
+                        if !snapshot::restore(govee_queue, snapshot_id.load(Ordering::Relaxed)) {
+                            println!("no snapshot from before the sunrise, activating daylamp and turning off instead");
+                            state::daylamp(govee_queue);
+                            govee_queue.push_back(SetState::Power(false));
+                        }
+                    })
+                );
+            } else {
+                part(
+                    "daylamp => turn off",
+                    i32::from(stay_on_for_min),
+                    scene::state_amount(scene::DAYLAMP) + 1,
+                    Arc::new(|govee_queue| {
+                        state::daylamp(govee_queue);
+                        govee_queue.push_back(SetState::Power(false));
+                    })
+                );
+            }
+        },
+        TimerAction::Sunset { duration_min, start, end, curve } => {
+            let sunset_duration = Duration::from_secs(u64::from(duration_min) * 60);
+            part(
+                "sunset",
+                0,
+                state::sunset_state_amount(sunset_duration, end),
+                Arc::new(move |govee_queue| {
+                    let from = state::sunset_start(start, &govee_queue.expected());
+                    state::sunset(govee_queue, sunset_duration, from, end, curve);
+                })
+            );
+        },
+        TimerAction::Reminder => {
+            part("reminder", 0, state::reminder_state_amount(config::reminder_pattern()), Arc::new(state::reminder));
+        },
+        TimerAction::Nightlamp => {
+            part("nightlamp on", 0, scene::state_amount(scene::NIGHTLAMP), Arc::new(state::nightlamp));
+        },
+        TimerAction::Daylamp => {
+            part("daylamp on", 0, scene::state_amount(scene::DAYLAMP), Arc::new(state::daylamp));
+        },
+        TimerAction::PowerState { power } => {
+            part("set power", 0, 1, Arc::new(move |govee_queue|
+                govee_queue.push_back(SetState::Power(power))));
+        },
+        TimerAction::BrightnessState { brightness } => {
+            part("set brightness", 0, 1, Arc::new(move |govee_queue|
+                govee_queue.push_back(SetState::Brightness(brightness))));
+        },
+        TimerAction::ColorState { r, g, b } => {
+            part("set color", 0, 1, Arc::new(move |govee_queue|
+                govee_queue.push_back(SetState::Color((r, g, b)))));
+        },
+        TimerAction::ColorTemperatureState { kelvin } => {
+            part("set color temperature", 0, 1, Arc::new(move |govee_queue|
+                govee_queue.push_back(SetState::ColorTemperature(kelvin))));
+        },
+        TimerAction::Program { ref name } => {
+            // program is looked up when running, so changes to it apply to the timer
+            let steps = program::get(name).map(|program| program.expand()).unwrap_or_default();
+            let name = name.clone();
+            let program = part("program", 0, steps.len(), Arc::new(move |govee_queue|
+                program::run(govee_queue, &name)));
+            let planned_min = program::duration(&steps).as_secs().div_ceil(60);
+            planned = Some((program, i32::try_from(planned_min).unwrap_or(i32::MAX)));
+        },
+        TimerAction::Scene { ref name } => {
+            // scene is looked up when activating, so changes to it apply to the timer
+            let govee_calls = scene::state_amount(name);
+            let name = name.clone();
+            part("scene", 0, govee_calls, Arc::new(move |govee_queue|
+                scene::activate(govee_queue, &name)));
+        },
+    }
+
+    if let Some((index, minutes)) = planned {
+        parts[index].planned_min = minutes;
+    }
+
+    parts
+}
+
+/// if a sunrise of an enabled `Timer` is currently running or staying on:
+/// return the id of that timer and the date/time the sunrise finishes.
+pub fn active_sunrise(timers: &[Timer], now: &NaiveDateTime) -> Option<(u32, NaiveDateTime)> {
+    timers.iter()
+        .filter(|timer| timer.enable)
+        .find_map(|timer| {
+            let TimerAction::Sunrise { duration_min, stay_on_for_min, .. } = timer.action else {
+                return None;
+            };
+            // earliest finished sunrise that is still staying on or yet to come
+            let finish = timer.next_match_after(&(*now - TimeDelta::minutes(stay_on_for_min.into())))?;
+            let start = finish - TimeDelta::minutes(duration_min.into());
+            let active = start <= *now && timer.suppression_reason(finish.date()).is_none();
+            active.then_some((timer.id, finish))
+        })
+}
+
 /// if a timer matches the current date/time: push its function to the function queue.
 /// update `last_checked` with the current time if timers have been checked.
-pub async fn check_timers(simple_timers: &SimpleTimers, function_queue: &fn_queue::Queue, last_checked: &mut TimeDay) {
-    let now = TimeDay::now();
+pub async fn check_timers(
+    simple_timers: &SimpleTimers,
+    function_queue: &fn_queue::Queue,
+    last_checked: &mut TimeDay,
+    conflicts: &mut Conflicts
+) {
+    let now_datetime = timeday::local_now();
+    let now = TimeDay::from_datetime(&now_datetime);
     // if timers have already been checked this minute
     if now == *last_checked {
         return;
     }
+    let now_datetime = timeday::truncate_to_minute(&now_datetime);
+
+    for part in conflicts.take_due(now_datetime) {
+        println!("queueing delayed {} of timer {}, calling function...", part.description, part.window.timer);
+        fn_queue::enqueue(function_queue, part.function).await;
+        conflicts.windows.push(part.window);
+    }
 
     #[allow(clippy::significant_drop_in_scrutinee)]
     for timer in simple_timers.lock().await.iter() {
-        if timer.timeday.get_days().contains(&now.get_days()[0])
-        && timer.timeday.get_hour() == now.get_hour()
-        && timer.timeday.get_minute() == now.get_minute() {
+        if timer.trigger.matches(&now_datetime) {
+            // date of the parent timer occurrence this timer belongs to
+            let occurrence = (now_datetime - TimeDelta::minutes(timer.shift_min.into())).date();
+            if timer.suppression.applies_to(occurrence) {
+                println!("matched timer for {}, but occurrence on {occurrence} of timer {} is skipped", timer.trigger, timer.parent);
+                continue;
+            }
+            match conflicts.decide(timer, now_datetime) {
+                Decision::Run => (),
+                Decision::Preempt => {
+                    println!("matched timer for {}, preempting other timers", timer.trigger);
+                    fn_queue::enqueue(function_queue, Arc::new(|govee_queue| {
+                        println!("{} elements in govee queue, clearing to preempt...", govee_queue.len());
+                        govee_queue.clear();
+                    })).await;
+                },
+                Decision::Delay(at) => {
+                    println!("matched timer for {}, delaying until {at} because of a conflict", timer.trigger);
+                    continue;
+                },
+                Decision::Skip(reason) => {
+                    println!("matched timer for {}, but {reason}", timer.trigger);
+                    continue;
+                }
+            }
             fn_queue::enqueue(function_queue, Arc::clone(&timer.function)).await;
-            println!("matched timer for {}, calling function...", timer.timeday);
+            println!("matched timer for {}, calling function...", timer.trigger);
         }
     }
 
     *last_checked = now;
 }
 
+/// version of `timers` for optimistic concurrency, to be used as quoted `ETag`.
+/// sha256 hash of the timers as they are written to the data file.
+pub fn version(timers: &[Timer]) -> String {
+    sha256::digest(serde_json::to_string(timers).unwrap())
+}
+
 /// serialize `timers` as json and write it to [`crate::constants::DATA_FILE_NAME`]
 pub async fn write_timers_to_file(timers: &Timers) {
-    // build path
-    let path = dirs_next::data_dir();
-    if path.is_none() { return; } // ignore if path can't be determined
-    let mut path = path.unwrap();
-    path.push(crate::constants::DATA_FILE_NAME);
-
-    let content = serde_json::to_string(&*timers.lock().await).unwrap();
-
-    // try to write file as a "fire and forget" as it's result is irrelevant here
-    tokio::spawn(async move {
-        let _ = tokio::fs::write(path, content).await;
-    });
+    data_file::write(crate::constants::DATA_FILE_NAME, &*timers.lock().await);
 }
 
 #[cfg(test)]
@@ -272,26 +950,181 @@ mod tests {
     use super::*;
     use tokio::test; // async tests
 
+    /// linear sunrise with constant hue, turning off afterwards
+    const fn sunrise(duration_min: u16, stay_on_for_min: u16, sleep_min: u16, nightlamp_min: u16) -> TimerAction {
+        TimerAction::Sunrise {
+            duration_min, stay_on_for_min, sleep_min, nightlamp_min,
+            brightness_curve: Curve::Linear,
+            color_curve: Curve::Linear,
+            color_mode: state::SunriseColorMode::Hsv,
+            hue_start: None,
+            hue_stop: None,
+            restore_afterwards: false
+        }
+    }
+
+    /// enabled timer 1 at `timeday` without calendars, skipped dates or conflict policy
+    fn alarm(timeday: TimeDay, action: TimerAction) -> Timer {
+        Timer {
+            id: 1,
+            name: String::from("alarm"),
+            enable: true,
+            timeday: Some(timeday),
+            solar: None,
+            cron: None,
+            action,
+            skipped_date: None,
+            paused_until: None,
+            suppress_on: vec![],
+            only_on: vec![],
+            conflict: ConflictPolicy::Ignore
+        }
+    }
+
     #[test]
     #[allow(clippy::significant_drop_tightening)]
     async fn sunrise_timer_processing() {
         let simple_timers: SimpleTimers = Arc::new(Mutex::new(vec![]));
-        let timers: Timers = Arc::new(Mutex::new(vec![Timer {
-            enable: true,
-            timeday: TimeDay::new(7, 0, vec![0]),
-            action: TimerAction::Sunrise {
-                duration_min: 20,
-                stay_on_for_min: 5,
-                sleep_min: (60 * 8) + 30,
-                nightlamp_min: 60
-            }
-        }]));
+        let timers: Timers = Arc::new(Mutex::new(vec![alarm(TimeDay::new(7, 0, vec![0]), sunrise(20, 5, (60 * 8) + 30, 60))]));
         process_timers(&timers, &simple_timers).await;
         let simple_timers = simple_timers.lock().await;
         assert_eq!(simple_timers.len(), 4);
-        assert!(simple_timers.iter().any(|t| t.timeday == TimeDay::new(21, 30, vec![6])));
-        assert!(simple_timers.iter().any(|t| t.timeday == TimeDay::new(22, 30, vec![6])));
-        assert!(simple_timers.iter().any(|t| t.timeday == TimeDay::new( 6, 40, vec![0])));
-        assert!(simple_timers.iter().any(|t| t.timeday == TimeDay::new( 7,  5, vec![0])));
+        assert!(simple_timers.iter().any(|t| t.trigger == Trigger::Weekly(TimeDay::new(21, 30, vec![6]))));
+        assert!(simple_timers.iter().any(|t| t.trigger == Trigger::Weekly(TimeDay::new(22, 30, vec![6]))));
+        assert!(simple_timers.iter().any(|t| t.trigger == Trigger::Weekly(TimeDay::new( 6, 40, vec![0]))));
+        assert!(simple_timers.iter().any(|t| t.trigger == Trigger::Weekly(TimeDay::new( 7,  5, vec![0]))));
+    }
+
+    #[test]
+    async fn next_occurrence_includes_nightlamp() {
+        let timer = alarm(TimeDay::new(7, 0, vec![0, 1, 2, 3, 4, 5, 6]), sunrise(20, 5, 8 * 60, 60));
+        // nightlamp of the sunrise on the next day has already started
+        let now = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(22, 30, 0).unwrap();
+        let next = timer.next_occurrence(&now).unwrap();
+        assert_eq!(next, NaiveDate::from_ymd_opt(2024, 1, 3).unwrap().and_hms_opt(7, 0, 0).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    async fn active_sunrise_while_staying_on() {
+        let timer = alarm(TimeDay::new(7, 0, vec![0]), sunrise(20, 5, 0, 0));
+        let timers = [timer];
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        assert!(active_sunrise(&timers, &monday.and_hms_opt(6, 39, 0).unwrap()).is_none());
+        assert!(active_sunrise(&timers, &monday.and_hms_opt(6, 40, 0).unwrap()).is_some());
+        assert!(active_sunrise(&timers, &monday.and_hms_opt(7, 4, 0).unwrap()).is_some());
+        assert!(active_sunrise(&timers, &monday.and_hms_opt(7, 5, 0).unwrap()).is_none());
+    }
+
+    #[test]
+    async fn cron_sunrise_is_shifted() {
+        let timer = Timer {
+            timeday: None,
+            cron: Some(String::from("0 7 * * MON#1")),
+            ..alarm(TimeDay::new(7, 0, vec![0]), sunrise(20, 5, 0, 0))
+        };
+        let today = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let simple_timers = generate_simple_timers(&timer, today);
+        let sunrise = simple_timers.iter().find(|t| t.description == "sunrise").unwrap();
+        assert!(sunrise.trigger.matches(&today.and_hms_opt(6, 40, 0).unwrap()));
+        assert!(!sunrise.trigger.matches(&today.and_hms_opt(7, 0, 0).unwrap()));
+        // second monday
+        assert!(!sunrise.trigger.matches(&NaiveDate::from_ymd_opt(2024, 1, 8).unwrap().and_hms_opt(6, 40, 0).unwrap()));
+    }
+
+    #[test]
+    async fn sunrise_schedule() {
+        let timer = Timer {
+            skipped_date: NaiveDate::from_ymd_opt(2024, 1, 2),
+            ..alarm(TimeDay::new(7, 0, vec![0, 1]), sunrise(20, 5, 0, 0))
+        };
+        // monday 6:50 until tuesday 7:00
+        let from = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(6, 50, 0).unwrap();
+        let to = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap().and_hms_opt(7, 0, 0).unwrap();
+        let timers = [timer];
+        let events = schedule(&timers, &from, &to).into_iter()
+            .map(|e| (e.datetime.format("%d %H:%M").to_string(), e.description, e.suppressed.is_some()))
+            .collect_vec();
+        assert_eq!(events, vec![
+            (String::from("01 07:05"), String::from("daylamp => turn off"), false),
+            (String::from("02 06:40"), String::from("sunrise"), true),
+        ]);
+        // once for the occurrence, not for each of its parts
+        let suppressed = suppressed_occurrences(&timers, &from, &(to + TimeDelta::days(1))).into_iter()
+            .map(|o| (o.datetime.format("%d %H:%M").to_string(), o.reason))
+            .collect_vec();
+        assert_eq!(suppressed, vec![(String::from("02 07:00"), String::from("skipped"))]);
+    }
+
+    #[test]
+    async fn ids_of_old_and_duplicate_timers() {
+        let action = r#""action": { "type": "PowerState", "params": { "power": true } }"#;
+        let timeday = r#""timeday": { "hour": 7, "minute": 0, "days": [0] }"#;
+        let json = format!("[
+            {{ \"enable\": true, {timeday}, {action} }},
+            {{ \"id\": 4, \"name\": \"alarm\", \"enable\": true, {timeday}, {action} }},
+            {{ \"id\": 4, \"enable\": false, {timeday}, {action} }}
+        ]");
+        let mut timers = serde_json::from_str::<Vec<Timer>>(&json).unwrap();
+        assign_ids(&mut timers);
+        let ids = timers.iter().map(|t| (t.id, t.name.as_str())).collect_vec();
+        assert_eq!(ids, vec![(5, "timer 5"), (4, "alarm"), (6, "timer 6")]);
+    }
+
+    #[test]
+    async fn version_changes_with_timers() {
+        let mut timers = vec![alarm(TimeDay::new(7, 0, vec![0]), TimerAction::Reminder)];
+        let old_version = version(&timers);
+        assert_eq!(old_version, version(&timers.clone()));
+        timers[0].set_enable(false);
+        assert_ne!(old_version, version(&timers));
+    }
+
+    #[test]
+    async fn conflict_policies() {
+        let at = |hour, minute| NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        let today = at(0, 0).date();
+        let generate = |conflict| {
+            let reminder = Timer { id: 2, conflict, ..alarm(TimeDay::new(6, 50, vec![0]), TimerAction::Reminder) };
+            [alarm(TimeDay::new(7, 0, vec![0]), sunrise(20, 5, 0, 0)), reminder].iter()
+                .flat_map(|timer| generate_simple_timers(timer, today))
+                .collect_vec()
+        };
+        // sunrise, daylamp => turn off, reminder
+        let timers = generate(ConflictPolicy::Reject);
+        let mut conflicts = Conflicts::default();
+        assert_eq!(conflicts.decide(&timers[0], at(6, 40)), Decision::Run);
+        assert!(matches!(conflicts.decide(&timers[2], at(6, 50)), Decision::Skip(_)));
+
+        let timers = generate(ConflictPolicy::QueueAfter);
+        let mut conflicts = Conflicts::default();
+        assert_eq!(conflicts.decide(&timers[0], at(6, 40)), Decision::Run);
+        assert_eq!(conflicts.decide(&timers[2], at(6, 50)), Decision::Delay(at(7, 0)));
+        assert!(conflicts.take_due(at(6, 59)).is_empty());
+        assert_eq!(conflicts.take_due(at(7, 0)).len(), 1);
+
+        let timers = generate(ConflictPolicy::Preempt);
+        let mut conflicts = Conflicts::default();
+        assert_eq!(conflicts.decide(&timers[0], at(6, 40)), Decision::Run);
+        assert_eq!(conflicts.decide(&timers[2], at(6, 50)), Decision::Preempt);
+        // rest of the preempted sunrise
+        assert!(matches!(conflicts.decide(&timers[1], at(7, 5)), Decision::Skip(_)));
+
+        let timers = generate(ConflictPolicy::Ignore);
+        let mut conflicts = Conflicts::default();
+        assert_eq!(conflicts.decide(&timers[0], at(6, 40)), Decision::Run);
+        assert_eq!(conflicts.decide(&timers[2], at(6, 50)), Decision::Run);
+    }
+
+    #[test]
+    async fn overlapping_windows() {
+        let timers = [
+            alarm(TimeDay::new(7, 0, vec![0]), sunrise(20, 5, 0, 0)),
+            Timer { id: 2, ..alarm(TimeDay::new(6, 50, vec![0]), TimerAction::Reminder) },
+            Timer { id: 3, ..alarm(TimeDay::new(7, 1, vec![0]), TimerAction::Reminder) }
+        ];
+        let from = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let overlaps = overlaps(&timers, &from, &(from + TimeDelta::days(1)));
+        assert_eq!(overlaps.len(), 1);
+        assert!(overlaps[0].starts_with("\"reminder\" of timer 2 at 2024-01-01 06:50"));
+    }
+}
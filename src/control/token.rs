@@ -0,0 +1,176 @@
+use utoipa::ToSchema;
+use itertools::Itertools;
+use chrono::NaiveDateTime;
+use std::{collections::BTreeMap, sync::RwLock};
+use serde::{Serialize, Deserialize};
+use subtle::ConstantTimeEq;
+use crate::constants::TOKENS_FILE_NAME;
+use crate::util::{config, data_file, timeday, govee_secrets::api_key};
+
+/// API tokens by name, loaded from [`TOKENS_FILE_NAME`]
+static TOKENS: RwLock<BTreeMap<String, StoredToken>> = RwLock::new(BTreeMap::new());
+
+/// what a token may be used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema)]
+pub enum Scope {
+    /// get the state of the lamp, timers, programs, scenes and countdowns
+    #[serde(rename = "read")]
+    Read,
+    /// change the state of the lamp, run programs and scenes, snooze
+    #[serde(rename = "control")]
+    Control,
+    /// change timers, programs, scenes and countdowns
+    #[serde(rename = "timers:write")]
+    TimersWrite,
+    /// create and revoke tokens
+    #[serde(rename = "admin")]
+    Admin
+}
+
+/// token as saved in the data dir, only its hash is kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredToken {
+    /// sha256 of the token
+    hash: String,
+    scopes: Vec<Scope>,
+    created_at: NaiveDateTime
+}
+
+/// token without secret, as listed by the admin routes
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokenInfo {
+    name: String,
+    scopes: Vec<Scope>,
+    #[schema(value_type = String, example = "2024-01-01T06:00:00")]
+    created_at: NaiveDateTime
+}
+
+/// newly created token, the only time its secret is shown
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedToken {
+    name: String,
+    scopes: Vec<Scope>,
+    /// use as bearer token. it can't be shown again.
+    token: String
+}
+
+/// load tokens from file. use none when running into problems.
+pub fn load_tokens() {
+    match data_file::read::<BTreeMap<String, StoredToken>>(TOKENS_FILE_NAME) {
+        Ok(tokens) => {
+            println!("SETUP: successfully loaded {} API token(s) from file", tokens.len());
+            *TOKENS.write().unwrap() = tokens;
+        },
+        Err(message) => println!("SETUP: {message} for API tokens, using no API tokens...")
+    }
+}
+
+pub fn get_all() -> Vec<TokenInfo> {
+    TOKENS.read().unwrap().iter()
+        .map(|(name, token)| TokenInfo { name: name.clone(), scopes: token.scopes.clone(), created_at: token.created_at })
+        .collect()
+}
+
+/// create token with random secret and save all tokens. none if a token with this name exists already.
+pub fn create(name: String, scopes: Vec<Scope>) -> Option<CreatedToken> {
+    let mut tokens = TOKENS.write().unwrap();
+    let created = insert(&mut tokens, name, scopes)?;
+    data_file::write(TOKENS_FILE_NAME, &*tokens);
+    Some(created)
+}
+
+/// `token create [--scope SCOPE...] NAME` on the command line: create a token, e.g. the first admin token,
+/// and save it before returning. return error message on failure.
+pub fn create_from_args(args: &[String]) -> Result<CreatedToken, String> {
+    const USAGE: &str = "usage: lamp-server token create [--scope read|control|timers:write|admin]... NAME";
+    let ["create", args @ ..] = &args.iter().map(String::as_str).collect_vec()[..] else {
+        return Err(USAGE.to_string());
+    };
+    let mut scopes = Vec::new();
+    let mut name = None;
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        if arg == "--scope" {
+            let scope = args.next().ok_or(USAGE)?;
+            scopes.push(serde_json::from_value(serde_json::Value::from(*scope))
+                .map_err(|_| format!("unknown scope {scope:?}\n{USAGE}"))?);
+        } else if name.is_none() && !arg.starts_with("--") {
+            name = Some(arg.to_string());
+        } else {
+            return Err(USAGE.to_string());
+        }
+    }
+    let name = name.ok_or(USAGE)?;
+    data_file::check_name(&name)?;
+    if scopes.is_empty() {
+        return Err(format!("a token needs at least one scope\n{USAGE}"));
+    }
+
+    load_tokens();
+    let mut tokens = TOKENS.write().unwrap();
+    let created = insert(&mut tokens, name, scopes).ok_or("a token with this name exists already")?;
+    data_file::write_now(TOKENS_FILE_NAME, &*tokens)?;
+    Ok(created)
+}
+
+/// add token with random secret to `tokens`. none if a token with this name exists already.
+fn insert(tokens: &mut BTreeMap<String, StoredToken>, name: String, scopes: Vec<Scope>) -> Option<CreatedToken> {
+    if tokens.contains_key(&name) {
+        return None;
+    }
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).expect("operating system should provide random bytes");
+    let secret = format!("ls_{}", bytes.iter().map(|byte| format!("{byte:02x}")).join(""));
+
+    let scopes = scopes.into_iter().sorted().dedup().collect_vec();
+    tokens.insert(name.clone(), StoredToken {
+        hash: sha256::digest(&secret),
+        scopes: scopes.clone(),
+        created_at: timeday::local_now()
+    });
+    Some(CreatedToken { name, scopes, token: secret })
+}
+
+/// remove token and save all tokens. return whether it existed.
+pub fn revoke(name: &str) -> bool {
+    let mut tokens = TOKENS.write().unwrap();
+    let existed = tokens.remove(name).is_some();
+    if existed {
+        data_file::write(TOKENS_FILE_NAME, &*tokens);
+    }
+    existed
+}
+
+/// scopes of the bearer token `secret`, none if it is unknown.
+/// the legacy token (sha256 of the Govee API key, case insensitive) has every scope, unless disabled in the config file.
+/// comparisons take the same time regardless of how much of the token matches.
+pub fn scopes(secret: &str) -> Option<Vec<Scope>> {
+    let legacy = sha256::digest(api_key());
+    if config::legacy_token() && bool::from(secret.to_ascii_lowercase().as_bytes().ct_eq(legacy.as_bytes())) {
+        return Some(vec![Scope::Read, Scope::Control, Scope::TimersWrite, Scope::Admin]);
+    }
+    let hash = sha256::digest(secret);
+    // compare with every token instead of stopping at the match
+    TOKENS.read().unwrap().values()
+        .fold(None, |found, token| {
+            let matches = bool::from(token.hash.as_bytes().ct_eq(hash.as_bytes()));
+            if matches { Some(token.scopes.clone()) } else { found }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// arguments refused before the tokens file is touched
+    #[test]
+    fn invalid_command_line() {
+        let args = |args: &str| args.split_whitespace().map(String::from).collect_vec();
+        assert!(create_from_args(&args("list")).is_err());
+        assert!(create_from_args(&args("create --scope admin")).is_err());
+        assert!(create_from_args(&args("create --scope owner phone")).unwrap_err().starts_with("unknown scope \"owner\""));
+        assert!(create_from_args(&args("create phone")).unwrap_err().starts_with("a token needs at least one scope"));
+        assert!(create_from_args(&args("create --scope read phone tablet")).is_err());
+        assert!(create_from_args(&args("create --scope read phone!")).is_err());
+    }
+}
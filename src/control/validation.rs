@@ -0,0 +1,307 @@
+use utoipa::ToSchema;
+use serde::Serialize;
+use itertools::Itertools;
+use chrono::{NaiveDateTime, TimeDelta};
+use crate::control::{program, scene, state::SunriseColorMode, timer::{Timer, TimerAction, TimerEvent}};
+use crate::util::{config, cron::Cron, curve::Curve, govee_api, timeday};
+
+/// problem making a timer invalid
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ValidationError {
+    /// index of the timer in the validated array
+    timer: usize,
+    /// path of the invalid field in the timer, like "action.params.duration_min"
+    field: String,
+    message: &'static str
+}
+
+/// possible problem with valid timers
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ValidationWarning {
+    /// indices of the involved timers in the validated array
+    timers: Vec<usize>,
+    message: String
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ValidationReport {
+    /// whether there are no errors
+    valid: bool,
+    errors: Vec<ValidationError>,
+    warnings: Vec<ValidationWarning>,
+    /// highest number of states set with the Govee API by enabled valid timers on a single day of the next week
+    estimated_daily_govee_calls: usize
+}
+
+/// problems making `timer` invalid as path of the invalid field and message, in the order of the fields
+pub fn problems(timer: &Timer) -> Vec<(String, &'static str)> {
+    let mut problems = vec![];
+
+    // shorthand to add a problem if condition
+    let mut error_if = |condition: bool, field: &str, message| {
+        if condition {
+            problems.push((field.to_owned(), message));
+        }
+    };
+
+    error_if([timer.get_timeday().is_some(), timer.get_solar().is_some(), timer.get_cron().is_some()]
+        .into_iter().filter(|&given| given).count() != 1,
+        "", "exactly one of timeday, solar and cron has to be given");
+    if let Some(timeday) = timer.get_timeday() {
+        for (field, result) in [
+            ("timeday.hour", timeday::check_hour(*timeday.get_hour())),
+            ("timeday.minute", timeday::check_minute(*timeday.get_minute())),
+            ("timeday.days", timeday::check_days(timeday.get_days()))
+        ] {
+            if let Err(message) = result {
+                error_if(true, field, message);
+            }
+        }
+    }
+    if let Some(solar) = timer.get_solar() {
+        error_if(config::location().is_none(), "solar", "solar requires a location in the config file");
+        error_if(solar.get_offset_min().abs() > 720, "solar.offset_min", "offset_min must be from -720 to 720");
+        if let Err(message) = timeday::check_days(solar.get_days()) {
+            error_if(true, "solar.days", message);
+        }
+    }
+    if let Some(cron) = timer.get_cron() {
+        error_if(Cron::parse(cron).is_err(), "cron", "cron could not be parsed");
+    }
+    error_if(timer.get_calendars().any(|name| config::calendar(name).is_none()),
+        "suppress_on", "every calendar in suppress_on and only_on has to be defined in the config file");
+    problems.extend(action_problems(timer.get_action()));
+
+    problems
+}
+
+/// problems making `action` invalid as path of the invalid field (starting with "action") and message
+pub fn action_problems(action: &TimerAction) -> Vec<(String, &'static str)> {
+    let mut problems = vec![];
+
+    // shorthand to add a problem if condition
+    let mut error_if = |condition: bool, field: &str, message| {
+        if condition {
+            problems.push((field.to_owned(), message));
+        }
+    };
+
+    match *action {
+        TimerAction::Sunrise {
+            duration_min, stay_on_for_min, sleep_min, nightlamp_min,
+            brightness_curve, color_curve, color_mode, hue_start, hue_stop, ..
+        } => {
+            error_if(duration_min < 1, "action.params.duration_min", "duration_min has to be >= 1");
+            error_if(nightlamp_min > 0 && sleep_min < duration_min, "action.params.sleep_min",
+                "sleep_min has to be >= duration_min if nightlamp_min is > 0");
+            // limit for all: i16::MAX = 32767
+            error_if(sleep_min > 32767, "action.params.sleep_min", "sleep_min has to be <= 32767");
+            error_if(duration_min > 32767, "action.params.duration_min", "duration_min has to be <= 32767");
+            error_if(nightlamp_min > 32767, "action.params.nightlamp_min", "nightlamp_min has to be <= 32767");
+            error_if(stay_on_for_min > 32767, "action.params.stay_on_for_min", "stay_on_for_min has to be <= 32767");
+            // nightlamp turns on this long before the time of the timer
+            error_if(u32::from(sleep_min) + u32::from(nightlamp_min) > 32767, "action.params.nightlamp_min",
+                "sleep_min + nightlamp_min has to be <= 32767");
+            for (field, curve) in [("action.params.brightness_curve", brightness_curve), ("action.params.color_curve", color_curve)] {
+                error_if(matches!(curve, Curve::Gamma(gamma) if !(1 ..= 50).contains(&gamma)), field, "gamma has to be from 1 to 50");
+            }
+            error_if(hue_start.is_some_and(|hue| hue > 360), "action.params.hue_start", "hue_start has to be <= 360");
+            error_if(hue_stop.is_some_and(|hue| hue > 360), "action.params.hue_stop", "hue_stop has to be <= 360");
+            error_if(color_mode == SunriseColorMode::Blackbody && (hue_start.is_some() || hue_stop.is_some()),
+                "action.params.color_mode", "hue_start and hue_stop can only be used with color_mode Hsv");
+        },
+        TimerAction::Sunset { duration_min, curve, .. } => {
+            error_if(duration_min < 1, "action.params.duration_min", "duration_min has to be >= 1");
+            error_if(duration_min > 32767, "action.params.duration_min", "duration_min has to be <= 32767");
+            error_if(matches!(curve, Curve::Gamma(gamma) if !(1 ..= 50).contains(&gamma)), "action.params.curve", "gamma has to be from 1 to 50");
+        },
+        TimerAction::BrightnessState { brightness } => {
+            error_if(brightness < 1, "action.params.brightness", "brightness has to be >= 1");
+            error_if(brightness > 100, "action.params.brightness", "brightness has to be <= 100");
+        },
+        TimerAction::ColorTemperatureState { kelvin } => {
+            error_if(!govee_api::color_temperature_range().contains(&kelvin), "action.params.kelvin",
+                "kelvin is out of the color temperature range of the lamp model");
+        },
+        TimerAction::Program { ref name } => {
+            error_if(program::get(name).is_none(), "action.params.name", "program does not exist");
+        },
+        TimerAction::Scene { ref name } => {
+            error_if(scene::get(name).is_none(), "action.params.name", "scene does not exist");
+        },
+        _ => (),
+    }
+
+    problems
+}
+
+/// check all `timers` for errors, and enabled valid timers for warnings in the week after `from`
+pub fn validate(timers: &[Timer], from: &NaiveDateTime) -> ValidationReport {
+    let errors = timers.iter().enumerate()
+        .flat_map(|(index, timer)| problems(timer).into_iter()
+            .map(move |(field, message)| ValidationError { timer: index, field, message }))
+        .collect_vec();
+
+    let to = *from + TimeDelta::days(7);
+    // events that will not be left out, by index of their timer
+    let events = timers.iter().enumerate()
+        .filter(|(index, timer)| timer.is_enabled() && !errors.iter().any(|e| e.timer == *index))
+        .flat_map(|(index, timer)| timer.events(from, &to).into_iter()
+            .filter(|event| event.suppressed.is_none())
+            .map(move |event| (index, event)))
+        .sorted_by_key(|(_, event)| event.datetime)
+        .collect_vec();
+
+    let mut warnings = vec![];
+    warnings.extend(sunrise_warnings(timers, &events));
+    warnings.extend(collision_warnings(&events));
+
+    let estimated_daily_govee_calls = events.iter()
+        .into_grouping_map_by(|(_, event)| event.datetime.date())
+        .fold(0, |calls, _, (_, event)| calls + event.govee_calls)
+        .into_values()
+        .max().unwrap_or(0);
+    if estimated_daily_govee_calls > crate::constants::govee::DAILY_RATE_LIMIT {
+        warnings.push(ValidationWarning {
+            timers: events.iter().map(|(index, _)| *index).unique().sorted().collect(),
+            message: format!(
+                "timers set up to {estimated_daily_govee_calls} states on a single day, which exceeds the daily Govee API rate limit of {}",
+                crate::constants::govee::DAILY_RATE_LIMIT
+            )
+        });
+    }
+
+    ValidationReport { valid: errors.is_empty(), errors, warnings, estimated_daily_govee_calls }
+}
+
+/// warnings for overlapping sunrises and nightlamps turning on during a sunrise,
+/// at most one per pair of timers
+fn sunrise_warnings(timers: &[Timer], events: &[(usize, TimerEvent)]) -> Vec<ValidationWarning> {
+    // time from start of sunrise until turning off
+    let sunrise_windows = events.iter()
+        .filter(|(_, event)| event.description == "sunrise")
+        .filter_map(|(index, event)| match *timers[*index].get_action() {
+            TimerAction::Sunrise { duration_min, stay_on_for_min, .. } => Some((
+                *index,
+                event.datetime,
+                event.datetime + TimeDelta::minutes(i64::from(duration_min) + i64::from(stay_on_for_min))
+            )),
+            _ => None
+        })
+        .collect_vec();
+
+    let mut seen: Vec<(&'static str, usize, usize)> = vec![];
+    let mut warnings = vec![];
+    // shorthand to add a warning if there is none of the same kind for these timers
+    let mut warn = |kind, a: usize, b: usize, message: &dyn Fn() -> String| {
+        if !seen.contains(&(kind, a, b)) {
+            seen.push((kind, a, b));
+            warnings.push(ValidationWarning { timers: vec![a.min(b), a.max(b)], message: message() });
+        }
+    };
+
+    for ((a, a_start, a_stop), (b, b_start, b_stop)) in sunrise_windows.iter().tuple_combinations() {
+        if a != b && a_start <= b_stop && b_start <= a_stop {
+            warn("overlap", *a.min(b), *a.max(b), &|| format!(
+                "sunrises of timers {} and {} overlap on {}", a.min(b), a.max(b), a_start.max(b_start).date()));
+        }
+    }
+
+    for (nightlamp, event) in events.iter().filter(|(_, event)| event.description == "nightlamp on") {
+        for (sunrise, start, stop) in &sunrise_windows {
+            if *start <= event.datetime && event.datetime <= *stop {
+                warn("nightlamp", *nightlamp, *sunrise, &|| format!(
+                    "nightlamp of timer {nightlamp} turns on at {} after sunrise of timer {sunrise} started",
+                    event.datetime.format("%Y-%m-%d %H:%M")));
+            }
+        }
+    }
+
+    warnings
+}
+
+/// warnings for events happening in the same minute, at most one per combination of events
+fn collision_warnings(events: &[(usize, TimerEvent)]) -> Vec<ValidationWarning> {
+    let mut seen: Vec<Vec<(usize, &'static str)>> = vec![];
+    let mut warnings = vec![];
+
+    for (datetime, colliding) in &events.iter().chunk_by(|(_, event)| event.datetime) {
+        let colliding = colliding.map(|(index, event)| (*index, event.description)).collect_vec();
+        if colliding.len() < 2 || seen.contains(&colliding) {
+            continue;
+        }
+        warnings.push(ValidationWarning {
+            timers: colliding.iter().map(|(index, _)| *index).unique().sorted().collect(),
+            message: format!(
+                "{} happen in the same minute, e.g. at {}, so their states are queued in no particular order",
+                colliding.iter().map(|(index, description)| format!("{description:?} of timer {index}")).join(" and "),
+                datetime.format("%Y-%m-%d %H:%M")
+            )
+        });
+        seen.push(colliding);
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn timers(json: &str) -> Vec<Timer> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    /// monday
+    fn from() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn all_errors_with_field_paths() {
+        let timers = timers(r#"[
+            { "enable": true, "timeday": { "hour": 24, "minute": 0, "days": [0, 7] }, "action": { "type": "Reminder" } },
+            { "enable": true, "action": { "type": "BrightnessState", "params": { "brightness": 0 } } }
+        ]"#);
+        let report = validate(&timers, &from());
+        assert!(!report.valid);
+        let errors = report.errors.iter().map(|e| (e.timer, e.field.as_str())).collect_vec();
+        assert_eq!(errors, vec![(0, "timeday.hour"), (0, "timeday.days"), (1, ""), (1, "action.params.brightness")]);
+
+        let long_night: Vec<Timer> = serde_json::from_str(r#"[{ "enable": true, "timeday": { "hour": 7, "minute": 0, "days": [0] },
+            "action": { "type": "Sunrise", "params": { "duration_min": 30, "stay_on_for_min": 10, "sleep_min": 32767, "nightlamp_min": 32767 } } }]"#).unwrap();
+        let errors = validate(&long_night, &from()).errors.iter().map(|e| e.field.clone()).collect_vec();
+        assert_eq!(errors, vec![String::from("action.params.nightlamp_min")]);
+    }
+
+    #[test]
+    fn overlapping_sunrises_and_late_nightlamp() {
+        let timers = timers(r#"[
+            { "enable": true, "timeday": { "hour": 7, "minute": 0, "days": [0] },
+              "action": { "type": "Sunrise", "params": { "duration_min": 30, "stay_on_for_min": 10, "sleep_min": 0, "nightlamp_min": 0 } } },
+            { "enable": true, "timeday": { "hour": 7, "minute": 20, "days": [0] },
+              "action": { "type": "Sunrise", "params": { "duration_min": 30, "stay_on_for_min": 10, "sleep_min": 0, "nightlamp_min": 0 } } },
+            { "enable": true, "timeday": { "hour": 6, "minute": 55, "days": [0] }, "action": { "type": "Nightlamp" } },
+            { "enable": false, "timeday": { "hour": 6, "minute": 45, "days": [0] }, "action": { "type": "Nightlamp" } }
+        ]"#);
+        let report = validate(&timers, &from());
+        assert!(report.valid);
+        let warned = report.warnings.iter().map(|w| w.timers.clone()).collect_vec();
+        assert_eq!(warned, vec![vec![0, 1], vec![0, 2], vec![1, 2]]);
+    }
+
+    #[test]
+    fn collisions_and_daily_calls() {
+        let timers = timers(r#"[
+            { "enable": true, "timeday": { "hour": 7, "minute": 0, "days": [0, 1] }, "action": { "type": "Reminder" } },
+            { "enable": true, "cron": "0 7 * * MON", "action": { "type": "PowerState", "params": { "power": false } } }
+        ]"#);
+        let report = validate(&timers, &from());
+        // only reported once
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].timers, vec![0, 1]);
+        // reminder and power on mondays
+        assert_eq!(report.estimated_daily_govee_calls, crate::control::state::reminder_state_amount(config::reminder_pattern()) + 1);
+    }
+}
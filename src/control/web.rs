@@ -1,375 +1,1886 @@
 use std::sync::Arc;
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 use itertools::Itertools;
 use utoipa::{IntoParams, ToSchema};
 use crate::constants;
 #[allow(clippy::wildcard_imports)]
-use crate::control::{state, timer::*};
-use crate::util::{fn_queue, govee_api::{self, SetState}};
+use crate::control::{api_error::{self, ApiError, ErrorCode}, countdown, program, scene, snapshot, state, token, lockout, validation, timer::*};
+use crate::util::{config, curve::Curve, data_file, fn_queue, listener, reminder, timeday, govee_api::{self, SetState}};
 use axum::{
     Json,
     middleware,
+    routing::MethodRouter,
     http::HeaderMap,
     extract::{self, State},
     http::StatusCode as Code
 };
 
-type Response<T> = Result<T, (Code, &'static str)>;
+type Response<T> = Result<T, ApiError>;
+/// held while checking the version of the timers and changing them
+type TimersWriteLock = Arc<tokio::sync::Mutex<()>>;
+/// pending reminder of the last snooze. kept in memory only, so it is lost when the server restarts.
+type SnoozeTask = Arc<tokio::sync::Mutex<Option<tokio::task::JoinHandle<()>>>>;
 
-/// axum middleware to check authorization before evaluating a request
+/// axum middleware to check authorization before evaluating a request.
+/// addresses with too many failed authorizations are locked out for a while, whatever token they send.
 async fn validate_request(
+    State(scope): State<token::Scope>,
     headers: HeaderMap,
+    extract::ConnectInfo(Client(address)): extract::ConnectInfo<Client>,
     request: extract::Request,
     next: middleware::Next,
-) -> Result<axum::response::Response, (Code, &'static str)> {
-    check_authorization(&headers)?;
+) -> Response<axum::response::Response> {
+    use axum::{http::header::RETRY_AFTER, response::IntoResponse};
+    let forwarded_for = headers.get("x-forwarded-for").and_then(|value| value.to_str().ok());
+    let address = lockout::client_address(address.ip(), forwarded_for, config::trusted_proxies());
+    // before checking the token, so it can't be guessed while locked out
+    if let Some(duration) = lockout::locked_for(address) {
+        println!("WEB: refused authorization from locked out {address} for {} {}", request.method(), request.uri().path());
+        let error = ApiError::from((Code::TOO_MANY_REQUESTS, ErrorCode::LockedOut, "too many failed authorizations, try again later"));
+        return Ok(([(RETRY_AFTER, duration.as_secs().max(1).to_string())], error).into_response());
+    }
+    match check_authorization(&headers, scope) {
+        // only unknown tokens count, not malformed requests
+        Err(error) if error.status() == Code::UNAUTHORIZED => {
+            println!("WEB: failed authorization from {address} for {} {}: {error}", request.method(), request.uri().path());
+            if lockout::record_failure(address) {
+                println!("WEB: locked out {address} after too many failed authorizations");
+            }
+            return Err(error);
+        },
+        // a known token used for the wrong route doesn't reset the failures of others
+        Err(error) => return Err(error),
+        Ok(()) => lockout::record_success(address)
+    }
     // evaluate and return original request
     Ok(next.run(request).await)
 }
 
-fn check_authorization(headers: &HeaderMap) -> Response<()> {
-    use crate::util::govee_secrets::api_key;
+/// require a bearer token with `scope` for every method of `route`.
+/// every API route declares its scope this way, routes without it are public.
+fn scoped<S: Clone + Send + Sync + 'static>(scope: token::Scope, route: MethodRouter<S>) -> MethodRouter<S> {
+    route.route_layer(middleware::from_fn_with_state(scope, validate_request))
+}
+
+/// source address of a request, over HTTP or HTTPS
+#[derive(Debug, Clone, Copy)]
+struct Client(std::net::SocketAddr);
+impl extract::connect_info::Connected<axum::serve::IncomingStream<'_, tokio::net::TcpListener>> for Client {
+    fn connect_info(stream: axum::serve::IncomingStream<'_, tokio::net::TcpListener>) -> Self {
+        Self(*stream.remote_addr())
+    }
+}
+impl extract::connect_info::Connected<axum::serve::IncomingStream<'_, listener::TlsListener>> for Client {
+    fn connect_info(stream: axum::serve::IncomingStream<'_, listener::TlsListener>) -> Self {
+        Self(*stream.remote_addr())
+    }
+}
+
+/// check that the bearer token in `headers` is known and has `scope`
+fn check_authorization(headers: &HeaderMap, scope: token::Scope) -> Response<()> {
     let Some(value) = headers.get("authorization") else {
-        return Err((Code::BAD_REQUEST, "authorization header is missing"));
+        return Err((Code::BAD_REQUEST, ErrorCode::MissingAuthorization, "authorization header is missing").into());
     };
 
     let Ok(value) = value.to_str() else {
-        return Err((Code::BAD_REQUEST, "authorization header value contains characters that are not visible ASCII"));
+        return Err((Code::BAD_REQUEST, ErrorCode::InvalidAuthorization, "authorization header value contains characters that are not visible ASCII").into());
     };
 
     let Some(token) = value.strip_prefix("Bearer ") else {
-        return Err((Code::BAD_REQUEST, "authorization header value is not of type bearer"));
+        return Err((Code::BAD_REQUEST, ErrorCode::InvalidAuthorization, "authorization header value is not of type bearer").into());
     };
 
-    // check for expected token
-    if token.eq_ignore_ascii_case(sha256::digest(api_key()).as_str()) {
+    let Some(scopes) = token::scopes(token) else {
+        return Err((Code::UNAUTHORIZED, ErrorCode::UnknownToken, "bearer token is unknown or revoked").into());
+    };
+    if scopes.contains(&scope) {
         Ok(())
     } else {
-        Err((Code::UNAUTHORIZED, "expected sha256 hash of Govee API key as bearer token (case insensitive)"))
+        Err((Code::FORBIDDEN, ErrorCode::MissingScope, "bearer token does not have the scope required for this route").into())
+    }
+}
+
+/// axum middleware for optimistic concurrency of timer routes.
+/// requires requests changing timers to have an `If-Match` header with the current version,
+/// and adds the (new) version to responses as `ETag` header.
+async fn check_timers_version(
+    State((timers, write_lock)): State<(Timers, TimersWriteLock)>,
+    request: extract::Request,
+    next: middleware::Next,
+) -> axum::response::Response {
+    use axum::{http::{Method, header}, response::IntoResponse};
+
+    // make sure the timers don't change between checking the version and evaluating the request
+    let _write_guard = if request.method() == Method::GET {
+        None
+    } else {
+        let write_guard = write_lock.lock().await;
+        let current_version = version(&timers.lock().await);
+        let Some(value) = request.headers().get(header::IF_MATCH) else {
+            return ApiError::from((Code::PRECONDITION_REQUIRED, ErrorCode::VersionRequired,
+                "If-Match header with ETag of GET /timers is required to change timers")).into_response();
+        };
+        let Ok(value) = value.to_str() else {
+            return ApiError::from((Code::BAD_REQUEST, ErrorCode::InvalidVersion,
+                "If-Match header value contains characters that are not visible ASCII")).into_response();
+        };
+        let matches = value.split(',')
+            .map(|tag| tag.trim().trim_matches('"'))
+            .any(|tag| tag == "*" || tag == current_version);
+        if !matches {
+            println!("timers were changed in the meantime, current version is {current_version:?}");
+            let etag = format!("\"{current_version}\"");
+            return ([(header::ETAG, etag)], ApiError::version_mismatch(current_version)).into_response();
+        }
+        Some(write_guard)
+    };
+
+    let mut response = next.run(request).await;
+    let new_version = version(&timers.lock().await);
+    response.headers_mut().insert(header::ETAG, format!("\"{new_version}\"").parse().unwrap());
+    response
+}
+
+#[utoipa::path(
+    get,
+    path = "/state",
+    responses(
+        (status = 200,
+        description = "Successfully fetched current state of lamp.",
+        body = govee_api::GetState),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 500,
+        description = "Fetching state failed, likely because of Govee API rate limit."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn get_state() -> Response<Json<govee_api::GetState>> {
+    govee_api::get_state().await.map_or(
+        Err((Code::INTERNAL_SERVER_ERROR, ErrorCode::GoveeUnavailable, "could not get state. likely because of Govee API rate limit.").into()),
+        |state| Ok(Json(state))
+    )
+}
+
+/// response of routes queueing changes of the lamp
+#[derive(Debug, Serialize, ToSchema)]
+struct QueuedResponse {
+    /// id of the queued job, unique until the server restarts
+    job_id: u64,
+    /// what has been queued
+    message: String,
+    /// when all states of the job are estimated to be set with the Govee API
+    estimated_done_at: chrono::NaiveDateTime
+}
+impl QueuedResponse {
+    /// enqueue `function` appending `states` states to the govee queue
+    async fn enqueue(function_queue: &fn_queue::Queue, function: fn_queue::Element, states: usize, message: String) -> Json<Self> {
+        println!("{message}");
+        let estimated_done_at = fn_queue::estimate_done_at(states, false);
+        let job_id = fn_queue::enqueue(function_queue, function).await;
+        Json(Self { job_id, message, estimated_done_at })
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/v2/clear_govee_queue",
+    responses(
+        (status = 200,
+        description = "Successfully cleared queue of Govee API calls to make. Also queued setting brightness to default and turning lamp off.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_v2_clear_govee_queue(
+    State(function_queue): State<fn_queue::Queue>
+) -> Response<Json<QueuedResponse>> {
+    let message = String::from("queued clearing Govee API call queue, setting brightness and turning off");
+    println!("{message}");
+    let estimated_done_at = fn_queue::estimate_done_at(2, true);
+    let job_id = fn_queue::enqueue(&function_queue, Arc::new(|govee_queue| {
+        println!("{} elements in govee queue, clearing...", govee_queue.len());
+        govee_queue.clear();
+        println!("queueing setting default brightness and turning off...");
+        govee_queue.push_back(SetState::Brightness(constants::brightness::DAY));
+        govee_queue.push_back(SetState::Power(false));
+    })).await;
+    Ok(Json(QueuedResponse { job_id, message, estimated_done_at }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clear_govee_queue",
+    responses(
+        (status = 200,
+        description = "Deprecated, use `POST /v2/clear_govee_queue`. Only available if `legacy_get_routes` is enabled in the config file."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+#[deprecated(note = "use post_v2_clear_govee_queue")]
+async fn get_clear_govee_queue(
+    state: State<fn_queue::Queue>
+) -> Response<String> {
+    post_v2_clear_govee_queue(state).await.map(|Json(queued)| queued.message)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReminderQuery {
+    /// red part of a color instead of the one of the reminder scene, requires `g` and `b`
+    r: Option<u8>,
+    /// green part, requires `r` and `b`
+    g: Option<u8>,
+    /// blue part, requires `r` and `g`
+    b: Option<u8>,
+    /// how often the reminder turns on
+    #[param(minimum = 1, maximum = 20)]
+    pulses: Option<u8>,
+    /// API slots (about 6.5 seconds each) a pulse stays on
+    #[param(minimum = 1, maximum = 50)]
+    on_slots: Option<u8>,
+    /// API slots between pulses
+    #[param(minimum = 1, maximum = 50)]
+    off_slots: Option<u8>,
+    /// what happens after the last pulse
+    afterwards: Option<reminder::ReminderAfterwards>
+}
+#[utoipa::path(
+    post,
+    path = "/v2/activate_reminder",
+    params(ReminderQuery),
+    responses(
+        (status = 200,
+        description = "Successfully queued the reminder pattern. Values not given are taken from the config file, which defaults to bright orange color with high brightness being active for about 20 seconds.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Query parameters had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_v2_activate_reminder(
+    State(function_queue): State<fn_queue::Queue>,
+    extract::Query(query): extract::Query<ReminderQuery>
+) -> Response<Json<QueuedResponse>> {
+    let default = config::reminder_pattern();
+    let color = match (query.r, query.g, query.b) {
+        (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+        (None, None, None) => default.color,
+        _ => return Err((Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidColor, "r, g and b have to be given together").into())
+    };
+    let pattern = reminder::ReminderPattern {
+        color,
+        pulses: query.pulses.unwrap_or(default.pulses),
+        on_slots: query.on_slots.unwrap_or(default.on_slots),
+        off_slots: query.off_slots.unwrap_or(default.off_slots),
+        afterwards: query.afterwards.unwrap_or(default.afterwards)
+    };
+    pattern.validate().map_err(|message| (Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidReminderPattern, message))?;
+
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(move |govee_queue| state::reminder_pattern(govee_queue, pattern)),
+        state::reminder_state_amount(pattern),
+        String::from("queued reminder activation")
+    ).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/activate_reminder",
+    params(ReminderQuery),
+    responses(
+        (status = 200,
+        description = "Deprecated, use `POST /v2/activate_reminder`. Only available if `legacy_get_routes` is enabled in the config file."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Query parameters had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+#[deprecated(note = "use post_v2_activate_reminder")]
+async fn get_activate_reminder(
+    state: State<fn_queue::Queue>,
+    query: extract::Query<ReminderQuery>
+) -> Response<String> {
+    post_v2_activate_reminder(state, query).await.map(|Json(queued)| queued.message)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v2/activate_nightlamp",
+    responses(
+        (status = 200,
+        description = "Successfully queued setting brightness to default for night and color to nice warm white.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_v2_activate_nightlamp(
+    State(function_queue): State<fn_queue::Queue>
+) -> Response<Json<QueuedResponse>> {
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(state::nightlamp),
+        scene::state_amount(scene::NIGHTLAMP),
+        String::from("queued nightlamp activation")
+    ).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/activate_nightlamp",
+    responses(
+        (status = 200,
+        description = "Deprecated, use `POST /v2/activate_nightlamp`. Only available if `legacy_get_routes` is enabled in the config file."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+#[deprecated(note = "use post_v2_activate_nightlamp")]
+async fn get_activate_nightlamp(
+    state: State<fn_queue::Queue>
+) -> Response<String> {
+    post_v2_activate_nightlamp(state).await.map(|Json(queued)| queued.message)
+}
+
+#[utoipa::path(
+    post,
+    path = "/v2/activate_daylamp",
+    responses(
+        (status = 200,
+        description = "Successfully queued setting brightness to default for day and color to pleasant orange.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_v2_activate_daylamp(
+    State(function_queue): State<fn_queue::Queue>
+) -> Response<Json<QueuedResponse>> {
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(state::daylamp),
+        scene::state_amount(scene::DAYLAMP),
+        String::from("queued daylamp activation")
+    ).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/activate_daylamp",
+    responses(
+        (status = 200,
+        description = "Deprecated, use `POST /v2/activate_daylamp`. Only available if `legacy_get_routes` is enabled in the config file."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+#[deprecated(note = "use post_v2_activate_daylamp")]
+async fn get_activate_daylamp(
+    state: State<fn_queue::Queue>
+) -> Response<String> {
+    post_v2_activate_daylamp(state).await.map(|Json(queued)| queued.message)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SunsetQuery {
+    /// how long the sunset should be
+    #[param(minimum = 1, maximum = 32767)]
+    duration_min: u16,
+    /// defaults to `Daylamp`
+    start: Option<state::SunsetStart>,
+    /// defaults to `PowerOff`
+    end: Option<state::SunsetEnd>,
+    /// how brightness and color change, defaults to `Linear`. `Gamma` is only available for timers.
+    curve: Option<Curve>
+}
+#[utoipa::path(
+    post,
+    path = "/v2/activate_sunset",
+    params(SunsetQuery),
+    responses(
+        (status = 200,
+        description = "Successfully queued ramping from the start state down to deep orange with brightness for night over the given duration, then powering off or activating nightlamp.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Query parameters had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_v2_activate_sunset(
+    State(function_queue): State<fn_queue::Queue>,
+    extract::Query(query): extract::Query<SunsetQuery>
+) -> Response<Json<QueuedResponse>> {
+    if !(1 ..= 32767).contains(&query.duration_min) {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidDuration, "duration_min", "duration_min must be from 1 to 32767"));
+    }
+    let start = query.start.unwrap_or_default();
+    let end = query.end.unwrap_or_default();
+    let curve = query.curve.unwrap_or_default();
+    let duration = std::time::Duration::from_secs(u64::from(query.duration_min) * 60);
+
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(move |govee_queue| {
+            let from = state::sunset_start(start, &govee_queue.expected());
+            state::sunset(govee_queue, duration, from, end, curve);
+        }),
+        state::sunset_state_amount(duration, end),
+        format!("queued {} min sunset activation", query.duration_min)
+    ).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/activate_sunset",
+    params(SunsetQuery),
+    responses(
+        (status = 200,
+        description = "Deprecated, use `POST /v2/activate_sunset`. Only available if `legacy_get_routes` is enabled in the config file."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Query parameters had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+#[deprecated(note = "use post_v2_activate_sunset")]
+async fn get_activate_sunset(
+    state: State<fn_queue::Queue>,
+    query: extract::Query<SunsetQuery>
+) -> Response<String> {
+    post_v2_activate_sunset(state, query).await.map(|Json(queued)| queued.message)
+}
+
+#[utoipa::path(
+    get,
+    path = "/timers",
+    responses(
+        (status = 200,
+        description = "Successfully returned array of current timers.",
+        body = Vec<Timer>,
+        headers(("ETag" = String, description = "current version of the timers, required as If-Match header to change them"))),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn get_timers(
+    State(timers): State<Timers>
+) -> Response<Json<Vec<Timer>>> {
+    Ok(Json(timers.lock().await.clone()))
+}
+
+#[utoipa::path(
+    put,
+    path = "/timers",
+    params(("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version")),
+    // TimerAction (enum) cant implement IntoParams, so this doesnt work
+    //params(Vec<Timer>), 
+    responses(
+        (status = 200,
+        description = "Successfully set internal timers to provided timers. Duplicates were removed. Timers without id or with an id used by a previous timer got a new id."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn put_timers(
+    State(state): State<(Timers, SimpleTimers)>,
+    extract::Json(new_timers): extract::Json<Vec<Timer>>
+) -> Response<&'static str> {
+    // remove duplicates
+    let mut new_timers = new_timers.into_iter().unique().collect_vec();
+
+    for (index, timer) in new_timers.iter().enumerate() {
+        validate_timer(timer, &format!("[{index}]"))?;
+    }
+    assign_ids(&mut new_timers);
+
+    let (timers, simple_timers) = state;
+    *timers.lock().await = new_timers;
+    process_timers(&timers, &simple_timers).await;
+    write_timers_to_file(&timers).await;
+    Ok("timers updated.")
+}
+
+/// return first problem of `timer` with status code `UNPROCESSABLE_ENTITY` if it is invalid.
+/// its field path starts with `prefix`, like "[2]" for the third timer of an array.
+fn validate_timer(timer: &Timer, prefix: &str) -> Response<()> {
+    validation::problems(timer).into_iter().next().map_or(Ok(()), |(field, message)| {
+        let field = [prefix, &field].into_iter().filter(|part| !part.is_empty()).join(".");
+        Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidTimer, field, message))
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/timers/validate",
+    responses(
+        (status = 200,
+        description = "Successfully checked the provided timers without saving them. Returned all errors with the index of the timer and the path of the field, warnings about the enabled valid timers in the next week, and their estimated daily usage of the Govee API.",
+        body = validation::ValidationReport),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_timers_validate(
+    extract::Json(timers): extract::Json<Vec<Timer>>
+) -> Response<Json<validation::ValidationReport>> {
+    Ok(Json(validation::validate(&timers, &timeday::local_now())))
+}
+
+#[utoipa::path(
+    post,
+    path = "/timers",
+    params(("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version")),
+    responses(
+        (status = 201,
+        description = "Successfully added the provided timer. Returned it with its assigned id (an id in the request is ignored).",
+        body = Timer),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_timer(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Json(mut timer): extract::Json<Timer>
+) -> Response<(Code, Json<Timer>)> {
+    validate_timer(&timer, "")?;
+    // always assign a new id
+    timer.set_id(0);
+
+    let mut timers_guard = timers.lock().await;
+    timers_guard.push(timer);
+    assign_ids(&mut timers_guard);
+    let timer = timers_guard.last().unwrap().clone();
+    drop(timers_guard);
+
+    process_timers(&timers, &simple_timers).await;
+    write_timers_to_file(&timers).await;
+    println!("added timer {}", timer.get_id());
+    Ok((Code::CREATED, Json(timer)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/timers/{id}",
+    params(("id" = u32, Path, description = "id of the timer")),
+    responses(
+        (status = 200,
+        description = "Successfully returned the timer.",
+        body = Timer),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no timer with the given id."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn get_timer(
+    State(timers): State<Timers>,
+    extract::Path(id): extract::Path<u32>
+) -> Response<Json<Timer>> {
+    timers.lock().await.iter()
+        .find(|timer| timer.get_id() == id)
+        .map(|timer| Json(timer.clone()))
+        .ok_or_else(|| (Code::NOT_FOUND, ErrorCode::TimerNotFound, "there is no timer with this id").into())
+}
+
+#[utoipa::path(
+    put,
+    path = "/timers/{id}",
+    params(
+        ("id" = u32, Path, description = "id of the timer"),
+        ("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version")
+    ),
+    responses(
+        (status = 200,
+        description = "Successfully replaced the timer with the provided timer, keeping its id. Returned the new timer.",
+        body = Timer),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no timer with the given id."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn put_timer(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(id): extract::Path<u32>,
+    extract::Json(mut new_timer): extract::Json<Timer>
+) -> Response<Json<Timer>> {
+    validate_timer(&new_timer, "")?;
+    new_timer.set_id(id);
+    replace_timer(&timers, &simple_timers, new_timer).await.map(Json)
+}
+
+#[utoipa::path(
+    patch,
+    path = "/timers/{id}",
+    params(
+        ("id" = u32, Path, description = "id of the timer"),
+        ("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version")
+    ),
+    request_body(content = Object, content_type = "application/merge-patch+json",
+        description = "JSON merge patch (RFC 7396) to apply to the timer, like `{ \"enable\": false }`. the id can not be changed."),
+    responses(
+        (status = 200,
+        description = "Successfully applied the patch to the timer. Returned the new timer.",
+        body = Timer),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no timer with the given id."),
+        (status = 422,
+        description = "Patched timer had unexpected contents."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn patch_timer(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(id): extract::Path<u32>,
+    body: String
+) -> Response<Json<Timer>> {
+    let Ok(patch) = serde_json::from_str::<serde_json::Value>(&body) else {
+        return Err((Code::BAD_REQUEST, ErrorCode::InvalidRequest, "request body is not valid JSON").into());
+    };
+    let Json(timer) = get_timer(State(Arc::clone(&timers)), extract::Path(id)).await?;
+
+    let mut value = serde_json::to_value(timer).unwrap();
+    merge_patch(&mut value, patch);
+    let Ok(mut new_timer) = serde_json::from_value::<Timer>(value) else {
+        return Err((Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidPatch, "patched timer did not match expected structure").into());
+    };
+    validate_timer(&new_timer, "")?;
+    new_timer.set_id(id);
+    replace_timer(&timers, &simple_timers, new_timer).await.map(Json)
+}
+
+/// apply JSON merge patch (RFC 7396) to `target`
+fn merge_patch(target: &mut serde_json::Value, patch: serde_json::Value) {
+    use serde_json::Value;
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(serde_json::Map::new());
+    }
+    let Value::Object(target) = target else { unreachable!() };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(&key);
+        } else {
+            merge_patch(target.entry(key).or_insert(Value::Null), value);
+        }
+    }
+}
+
+/// replace the timer with the id of `new_timer`, then process and save all timers.
+/// return the new timer.
+async fn replace_timer(timers: &Timers, simple_timers: &SimpleTimers, new_timer: Timer) -> Response<Timer> {
+    let id = new_timer.get_id();
+    update_timer(timers, simple_timers, id, |timer| {
+        *timer = new_timer;
+        // name timer if name was removed
+        assign_ids(std::slice::from_mut(timer));
+        timer.clone()
+    }).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/timers/{id}",
+    params(
+        ("id" = u32, Path, description = "id of the timer"),
+        ("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version")
+    ),
+    responses(
+        (status = 200,
+        description = "Successfully deleted the timer."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no timer with the given id."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn delete_timer(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(id): extract::Path<u32>
+) -> Response<&'static str> {
+    let mut timers_guard = timers.lock().await;
+    let Some(index) = timers_guard.iter().position(|timer| timer.get_id() == id) else {
+        return Err((Code::NOT_FOUND, ErrorCode::TimerNotFound, "there is no timer with this id").into());
+    };
+    timers_guard.remove(index);
+    drop(timers_guard);
+
+    process_timers(&timers, &simple_timers).await;
+    write_timers_to_file(&timers).await;
+    println!("deleted timer {id}");
+    Ok("timer deleted.")
+}
+
+#[utoipa::path(
+    post,
+    path = "/timers/{id}/enable",
+    params(
+        ("id" = u32, Path, description = "id of the timer"),
+        ("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version")
+    ),
+    responses(
+        (status = 200,
+        description = "Successfully enabled the timer."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no timer with the given id."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_timer_enable(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(id): extract::Path<u32>
+) -> Response<&'static str> {
+    update_timer(&timers, &simple_timers, id, |timer| timer.set_enable(true)).await?;
+    Ok("timer enabled.")
+}
+
+#[utoipa::path(
+    post,
+    path = "/timers/{id}/disable",
+    params(
+        ("id" = u32, Path, description = "id of the timer"),
+        ("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version")
+    ),
+    responses(
+        (status = 200,
+        description = "Successfully disabled the timer."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no timer with the given id."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_timer_disable(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(id): extract::Path<u32>
+) -> Response<&'static str> {
+    update_timer(&timers, &simple_timers, id, |timer| timer.set_enable(false)).await?;
+    Ok("timer disabled.")
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SuppressedQuery {
+    /// how many days to look ahead, defaults to 14
+    #[param(minimum = 1, maximum = 366)]
+    days: Option<u16>
+}
+#[utoipa::path(
+    get,
+    path = "/timers/suppressed",
+    params(SuppressedQuery),
+    responses(
+        (status = 200,
+        description = "Successfully returned upcoming occurrences of enabled timers that will be left out, sorted by date and time.",
+        body = Vec<SuppressedOccurrence>),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Query parameters had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn get_suppressed_timers(
+    State(timers): State<Timers>,
+    extract::Query(query): extract::Query<SuppressedQuery>
+) -> Response<Json<Vec<SuppressedOccurrence>>> {
+    let days = query.days.unwrap_or(14);
+    if !(1 ..= 366).contains(&days) {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidRange, "days", "days must be from 1 to 366"));
+    }
+    let from = timeday::local_now();
+    let to = from + chrono::TimeDelta::days(days.into());
+    Ok(Json(suppressed_occurrences(&timers.lock().await, &from, &to)))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ScheduleQuery {
+    /// local date and time to start from, defaults to now
+    #[param(example = "2024-01-01T00:00:00")]
+    from: Option<chrono::NaiveDateTime>,
+    /// local date and time to stop at, defaults to 7 days after `from`.
+    /// has to be at most 31 days after `from`.
+    #[param(example = "2024-01-08T00:00:00")]
+    to: Option<chrono::NaiveDateTime>
+}
+#[utoipa::path(
+    get,
+    path = "/timers/schedule",
+    params(ScheduleQuery),
+    responses(
+        (status = 200,
+        description = "Successfully returned events of all enabled timers in the given range, sorted by date and time. Includes events that will be left out, with the reason.",
+        body = Vec<ScheduledEvent>),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Query parameters had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn get_timer_schedule(
+    State(timers): State<Timers>,
+    extract::Query(query): extract::Query<ScheduleQuery>
+) -> Response<Json<Vec<ScheduledEvent>>> {
+    let from = query.from.unwrap_or_else(timeday::local_now);
+    let to = query.to.unwrap_or(from + chrono::TimeDelta::days(7));
+    if to < from {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidRange, "to", "to must not be before from"));
+    }
+    if to - from > chrono::TimeDelta::days(31) {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidRange, "to", "to must be at most 31 days after from"));
+    }
+    Ok(Json(schedule(&timers.lock().await, &from, &to)))
+}
+
+/// apply `update` to the timer with `id`, then process and save all timers.
+/// return the result of `update`.
+async fn update_timer<T>(
+    timers: &Timers,
+    simple_timers: &SimpleTimers,
+    id: u32,
+    update: impl FnOnce(&mut Timer) -> T
+) -> Response<T> {
+    let mut timers_guard = timers.lock().await;
+    let Some(timer) = timers_guard.iter_mut().find(|timer| timer.get_id() == id) else {
+        return Err((Code::NOT_FOUND, ErrorCode::TimerNotFound, "there is no timer with this id").into());
+    };
+    let result = update(timer);
+    drop(timers_guard);
+
+    process_timers(timers, simple_timers).await;
+    write_timers_to_file(timers).await;
+    Ok(result)
+}
+
+#[utoipa::path(
+    post,
+    path = "/timers/{id}/skip_next",
+    params(
+        ("id" = u32, Path, description = "id of the timer"),
+        ("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version")
+    ),
+    responses(
+        (status = 200,
+        description = "Successfully skipped the next occurrence of the timer that has not started yet."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no timer with the given id."),
+        (status = 409,
+        description = "The timer has no upcoming occurrence, e.g. because its solar event does not happen in the next days."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_timer_skip_next(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(id): extract::Path<u32>
+) -> Response<String> {
+    let date = update_timer(&timers, &simple_timers, id, |timer| {
+        let date = timer.next_occurrence(&timeday::local_now())?.date();
+        timer.set_skipped_date(Some(date));
+        Some(date)
+    }).await?;
+    let Some(date) = date else {
+        return Err((Code::CONFLICT, ErrorCode::NoUpcomingOccurrence, "timer has no upcoming occurrence").into());
+    };
+    let message = format!("skipping occurrence of timer {id} on {date}");
+    println!("{message}");
+    Ok(message)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/timers/{id}/skip_next",
+    params(
+        ("id" = u32, Path, description = "id of the timer"),
+        ("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version")
+    ),
+    responses(
+        (status = 200,
+        description = "Successfully removed skipping an occurrence of the timer."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no timer with the given id."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn delete_timer_skip_next(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(id): extract::Path<u32>
+) -> Response<&'static str> {
+    update_timer(&timers, &simple_timers, id, |timer| timer.set_skipped_date(None)).await?;
+    Ok("not skipping any occurrence of timer anymore")
+}
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+struct PauseState {
+    /// skip all occurrences before this date. use null to resume.
+    until: Option<chrono::NaiveDate>
+}
+#[utoipa::path(
+    put,
+    path = "/timers/{id}/pause",
+    params(
+        ("If-Match" = String, Header, description = "ETag from a previous response of a timer route, or * to overwrite any version"),
+        ("id" = u32, Path, description = "id of the timer"),
+        PauseState
+    ),
+    responses(
+        (status = 200,
+        description = "Successfully paused the timer until the given date, or resumed it."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no timer with the given id."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+        (status = 412,
+        description = "Timers were changed since the version in If-Match. The current version is in the ETag header and the `current_version` field."),
+        (status = 428,
+        description = "If-Match header is missing."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn put_timer_pause(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(id): extract::Path<u32>,
+    extract::Json(pausestate): extract::Json<PauseState>
+) -> Response<&'static str> {
+    update_timer(&timers, &simple_timers, id, |timer| timer.set_paused_until(pausestate.until)).await?;
+    Ok(if pausestate.until.is_some() { "timer paused." } else { "timer resumed." })
+}
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+struct SnoozeState {
+    /// minutes until the reminder
+    #[ param(minimum = 1)]
+    #[schema(minimum = 1)]
+    minutes: u16
+}
+#[utoipa::path(
+    put,
+    path = "/snooze",
+    params(SnoozeState),
+    responses(
+        (status = 200,
+        description = "Successfully held the current state of the active sunrise, by removing its remaining states but no others, and queued a reminder after the given minutes. Snoozing again replaces the pending reminder. The job only holds the state, the reminder is queued when the snooze is over. The pending reminder is kept in memory only and lost when the server restarts.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 409,
+        description = "There is no active sunrise (including staying on) or pending snooze."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn put_snooze(
+    State((timers, simple_timers, function_queue, snooze_task, write_lock)): State<(Timers, SimpleTimers, fn_queue::Queue, SnoozeTask, TimersWriteLock)>,
+    extract::Json(snoozestate): extract::Json<SnoozeState>
+) -> Response<Json<QueuedResponse>> {
+    if snoozestate.minutes < 1 {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidDuration, "minutes", "minutes must be >= 1"));
+    }
+
+    // skipping the rest of the sunrise changes its timer, like the timer routes do with If-Match
+    let _write_guard = write_lock.lock().await;
+    let mut snooze_task = snooze_task.lock().await;
+    let snoozing = snooze_task.as_ref().is_some_and(|task| !task.is_finished());
+
+    let active_sunrise = active_sunrise(&timers.lock().await, &timeday::local_now());
+    let hold: fn_queue::Element = if let Some((id, finish)) = active_sunrise {
+        // leave out the rest of this occurrence, e.g. turning off after staying on
+        update_timer(&timers, &simple_timers, id, |timer| timer.set_skipped_date(Some(finish.date()))).await?;
+        Arc::new(|govee_queue| {
+            let removed = state::stop_sunrise(govee_queue);
+            println!("removed {removed} remaining sunrise states to hold current state...");
+        })
+    } else if snoozing {
+        // the state was held by the previous snooze already
+        Arc::new(|_| println!("replacing pending snooze..."))
+    } else {
+        return Err((Code::CONFLICT, ErrorCode::NothingToSnooze, "there is no active sunrise or pending snooze").into());
+    };
+    let job_id = fn_queue::enqueue(&function_queue, hold).await;
+
+    // replace pending reminder
+    if let Some(task) = snooze_task.take() {
+        task.abort();
+    }
+    let delay = std::time::Duration::from_secs(u64::from(snoozestate.minutes) * 60);
+    let function_queue = Arc::clone(&function_queue);
+    *snooze_task = Some(tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        println!("snooze is over");
+        fn_queue::enqueue(&function_queue, Arc::new(state::reminder)).await;
+    }));
+    drop(snooze_task);
+
+    let message = format!("snoozing for {} min", snoozestate.minutes);
+    println!("{message}");
+    let reminder_states = state::reminder_state_amount(config::reminder_pattern());
+    let estimated_done_at = fn_queue::estimate_done_at(reminder_states, true) + chrono::TimeDelta::from_std(delay).unwrap();
+    Ok(Json(QueuedResponse { job_id, message, estimated_done_at }))
+}
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+struct PowerState { power: bool }
+#[utoipa::path(
+    put,
+    path = "/power",
+    params(PowerState),
+    responses(
+        (status = 200,
+        description = "Successfully queued requested power state.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn put_power(
+    State(function_queue): State<fn_queue::Queue>,
+    extract::Json(powerstate): extract::Json<PowerState>
+) -> Response<Json<QueuedResponse>> {
+    let setstate = SetState::Power(powerstate.power);
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(move |govee_queue| govee_queue.push_back(setstate)),
+        1,
+        format!("queued {setstate:?}")
+    ).await)
+}
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+struct BrightnessState {
+    #[ param(minimum = 1, maximum = 100)]
+    #[schema(minimum = 1, maximum = 100)]
+    brightness: u8
+}
+#[utoipa::path(
+    put,
+    path = "/brightness",
+    params(BrightnessState),
+    responses(
+        (status = 200,
+        description = "Successfully queued requested brightness state.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn put_brightness(
+    State(function_queue): State<fn_queue::Queue>,
+    extract::Json(brightnessstate): extract::Json<BrightnessState>
+) -> Response<Json<QueuedResponse>> {
+    if brightnessstate.brightness < 1 || brightnessstate.brightness > 100 {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidBrightness, "brightness", "brightness must be from 1 to 100"));
+    }
+
+    let setstate = SetState::Brightness(brightnessstate.brightness);
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(move |govee_queue| govee_queue.push_back(setstate)),
+        1,
+        format!("queued {setstate:?}")
+    ).await)
+}
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+struct ColorState {
+    #[ param(minimum = 0, maximum = 255)]
+    #[schema(minimum = 0, maximum = 255)]
+    r: u8,
+    #[ param(minimum = 0, maximum = 255)]
+    #[schema(minimum = 0, maximum = 255)]
+    g: u8,
+    #[ param(minimum = 0, maximum = 255)]
+    #[schema(minimum = 0, maximum = 255)]
+    b: u8
+}
+#[utoipa::path(
+    put,
+    path = "/color",
+    params(ColorState),
+    responses(
+        (status = 200,
+        description = "Successfully queued requested color state.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn put_color(
+    State(function_queue): State<fn_queue::Queue>,
+    extract::Json(colorstate): extract::Json<ColorState>
+) -> Response<Json<QueuedResponse>> {
+    let setstate = SetState::Color((colorstate.r, colorstate.g, colorstate.b));
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(move |govee_queue| govee_queue.push_back(setstate)),
+        1,
+        format!("queued {setstate:?}")
+    ).await)
+}
+
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+struct ColorTemperatureState {
+    /// the supported range depends on the lamp model, usually from 2000 to 9000
+    #[ param(minimum = 1000, maximum = 10000)]
+    #[schema(minimum = 1000, maximum = 10000)]
+    kelvin: u16
+}
+#[utoipa::path(
+    put,
+    path = "/color_temperature",
+    params(ColorTemperatureState),
+    responses(
+        (status = 200,
+        description = "Successfully queued requested color temperature state.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents, like a color temperature the lamp model does not support."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn put_color_temperature(
+    State(function_queue): State<fn_queue::Queue>,
+    extract::Json(colortemperaturestate): extract::Json<ColorTemperatureState>
+) -> Response<Json<QueuedResponse>> {
+    if !govee_api::color_temperature_range().contains(&colortemperaturestate.kelvin) {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidColorTemperature, "kelvin", "kelvin is out of the color temperature range of the lamp model"));
     }
+
+    let setstate = SetState::ColorTemperature(colortemperaturestate.kelvin);
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(move |govee_queue| govee_queue.push_back(setstate)),
+        1,
+        format!("queued {setstate:?}")
+    ).await)
 }
 
 #[utoipa::path(
     get,
-    path = "/state",
+    path = "/programs",
     responses(
         (status = 200,
-        description = "Successfully fetched current state of lamp.",
-        body = govee_api::GetState),
+        description = "Successfully returned all programs by name.",
+        body = std::collections::BTreeMap<String, program::Program>),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
-        (status = 500,
-        description = "Fetching state failed, likely because of Govee API rate limit."),
+        description = "Bearer authorization token is unknown or revoked."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn get_state() -> Response<Json<govee_api::GetState>> {
-    govee_api::get_state().await.map_or(
-        Err((Code::INTERNAL_SERVER_ERROR, "could not get state. likely because of Govee API rate limit.")),
-        |state| Ok(Json(state))
-    )
+async fn get_programs() -> Response<Json<std::collections::BTreeMap<String, program::Program>>> {
+    Ok(Json(program::get_all()))
 }
 
 #[utoipa::path(
     get,
-    path = "/clear_govee_queue",
+    path = "/programs/{name}",
+    params(("name" = String, Path, description = "name of the program")),
     responses(
         (status = 200,
-        description = "Successfully cleared queue of Govee API calls to make. Also queued setting brightness to default and turning lamp off."),
+        description = "Successfully returned the program.",
+        body = program::Program),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no program with the given name."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn get_clear_govee_queue(
-    State(function_queue): State<fn_queue::Queue>
-) -> Response<&'static str> {
-    let message = "queued clearing Govee API call queue, setting brightness and turning off";
-    println!("{message}");
-    fn_queue::enqueue(&function_queue, Arc::new(|govee_queue| {
-        println!("{} elements in govee queue, clearing...", govee_queue.len());
-        govee_queue.clear();
-        println!("queueing setting default brightness and turning off...");
-        govee_queue.push_back(SetState::Brightness(constants::brightness::DAY));
-        govee_queue.push_back(SetState::Power(false));
-    })).await;
-    Ok(message)
+async fn get_program(
+    extract::Path(name): extract::Path<String>
+) -> Response<Json<program::Program>> {
+    program::get(&name)
+        .map(Json)
+        .ok_or_else(|| (Code::NOT_FOUND, ErrorCode::ProgramNotFound, "there is no program with this name").into())
 }
 
 #[utoipa::path(
-    get,
-    path = "/activate_reminder",
+    put,
+    path = "/programs/{name}",
+    params(("name" = String, Path, description = "name of the program, with ASCII letters, digits, - and _")),
+    request_body = program::Program,
     responses(
         (status = 200,
-        description = "Successfully queued bright orange color with high brightness to be active for about 20 seconds."),
+        description = "Successfully replaced the program."),
+        (status = 201,
+        description = "Successfully added the program."),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Name or valid JSON request body had unexpected contents."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn get_activate_reminder(
-    State(function_queue): State<fn_queue::Queue>
-) -> Response<&'static str> {
-    let message = "queued reminder activation";
-    println!("{message}");
-    fn_queue::enqueue(&function_queue, Arc::new(state::reminder)).await;
-    Ok(message)
+async fn put_program(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(name): extract::Path<String>,
+    extract::Json(new_program): extract::Json<program::Program>
+) -> Response<(Code, &'static str)> {
+    data_file::check_name(&name).map_err(|message| (Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidName, message))?;
+    new_program.validate().map_err(|message| (Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidProgram, message))?;
+
+    let added = program::set(name.clone(), new_program);
+    // timers running this program need new estimates
+    process_timers(&timers, &simple_timers).await;
+    if added {
+        println!("added program {name:?}");
+        Ok((Code::CREATED, "program added."))
+    } else {
+        println!("replaced program {name:?}");
+        Ok((Code::OK, "program replaced."))
+    }
 }
 
 #[utoipa::path(
-    get,
-    path = "/activate_nightlamp",
+    delete,
+    path = "/programs/{name}",
+    params(("name" = String, Path, description = "name of the program")),
     responses(
         (status = 200,
-        description = "Successfully queued setting brightness to default for night and color to nice warm white."),
+        description = "Successfully deleted the program."),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no program with the given name."),
+        (status = 409,
+        description = "Program is still used by a timer."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn get_activate_nightlamp(
-    State(function_queue): State<fn_queue::Queue>
+async fn delete_program(
+    State(timers): State<Timers>,
+    extract::Path(name): extract::Path<String>
 ) -> Response<&'static str> {
-    let message = "queued nightlamp activation";
-    println!("{message}");
-    fn_queue::enqueue(&function_queue, Arc::new(state::nightlamp)).await;
-    Ok(message)
+    let used = timers.lock().await.iter()
+        .any(|timer| matches!(timer.get_action(), TimerAction::Program { name: used } if *used == name));
+    if used {
+        return Err((Code::CONFLICT, ErrorCode::ProgramInUse, "program is still used by a timer").into());
+    }
+    if !program::remove(&name) {
+        return Err((Code::NOT_FOUND, ErrorCode::ProgramNotFound, "there is no program with this name").into());
+    }
+    println!("deleted program {name:?}");
+    Ok("program deleted.")
+}
+
+#[utoipa::path(
+    post,
+    path = "/programs/{name}/run",
+    params(("name" = String, Path, description = "name of the program")),
+    responses(
+        (status = 200,
+        description = "Successfully queued all states of the program. The message tells how long running it will take.",
+        body = QueuedResponse),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no program with the given name."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_program_run(
+    State(function_queue): State<fn_queue::Queue>,
+    extract::Path(name): extract::Path<String>
+) -> Response<Json<QueuedResponse>> {
+    let Some(program) = program::get(&name) else {
+        return Err((Code::NOT_FOUND, ErrorCode::ProgramNotFound, "there is no program with this name").into());
+    };
+    let steps = program.expand();
+    let message = format!(
+        "queued program {name:?} with {} states, taking about {:.1} min",
+        steps.len(),
+        program::duration(&steps).as_secs_f32() / 60f32
+    );
+    let amount = steps.len();
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(move |govee_queue| program::push_states(govee_queue, &steps)),
+        amount,
+        message
+    ).await)
 }
 
 #[utoipa::path(
     get,
-    path = "/activate_daylamp",
+    path = "/scenes",
     responses(
         (status = 200,
-        description = "Successfully queued setting brightness to default for day and color to pleasant orange."),
+        description = "Successfully returned all scenes by name, built-in ones overridden by user-defined ones.",
+        body = std::collections::BTreeMap<String, scene::Scene>),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
+        description = "Bearer authorization token is unknown or revoked."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn get_activate_daylamp(
-    State(function_queue): State<fn_queue::Queue>
-) -> Response<&'static str> {
-    let message = "queued daylamp activation";
-    println!("{message}");
-    fn_queue::enqueue(&function_queue, Arc::new(state::daylamp)).await;
-    Ok(message)
+async fn get_scenes() -> Response<Json<std::collections::BTreeMap<String, scene::Scene>>> {
+    Ok(Json(scene::get_all()))
 }
 
 #[utoipa::path(
     get,
-    path = "/timers",
+    path = "/scenes/{name}",
+    params(("name" = String, Path, description = "name of the scene")),
     responses(
         (status = 200,
-        description = "Successfully returned array of current timers.",
-        body = Vec<Timer>),
+        description = "Successfully returned the scene.",
+        body = scene::Scene),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no scene with the given name."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn get_timers(
-    State(timers): State<Timers>
-) -> Response<Json<Vec<Timer>>> {
-    Ok(Json(timers.lock().await.clone()))
+async fn get_scene(
+    extract::Path(name): extract::Path<String>
+) -> Response<Json<scene::Scene>> {
+    scene::get(&name)
+        .map(Json)
+        .ok_or_else(|| (Code::NOT_FOUND, ErrorCode::SceneNotFound, "there is no scene with this name").into())
 }
 
 #[utoipa::path(
     put,
-    path = "/timers",
-    // TimerAction (enum) cant implement IntoParams, so this doesnt work
-    //params(Vec<Timer>), 
+    path = "/scenes/{name}",
+    params(("name" = String, Path, description = "name of the scene, with ASCII letters, digits, - and _. use the name of a built-in scene to override it.")),
+    request_body = scene::Scene,
     responses(
         (status = 200,
-        description = "Successfully set internal timers to provided timers. Duplicates were removed."),
+        description = "Successfully replaced the scene."),
+        (status = 201,
+        description = "Successfully added the scene."),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
+        description = "Bearer authorization token is unknown or revoked."),
         (status = 422,
-        description = "Valid JSON request body had unexpected contents."),
+        description = "Name or valid JSON request body had unexpected contents."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn put_timers(
-    State(state): State<(Timers, SimpleTimers)>,
-    extract::Json(new_timers): extract::Json<Vec<Timer>>
-) -> Response<&'static str> {
+async fn put_scene(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(name): extract::Path<String>,
+    extract::Json(new_scene): extract::Json<scene::Scene>
+) -> Response<(Code, &'static str)> {
+    data_file::check_name(&name).map_err(|message| (Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidName, message))?;
+    new_scene.validate().map_err(|message| (Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidScene, message))?;
 
-    /// return given error message with status code `UNPROCESSABLE_ENTITY` if condition
-    const fn error_if(condition: bool, message: &'static str) -> Response<()> {
-        if condition {
-            Err((Code::UNPROCESSABLE_ENTITY, message))
-        } else {
-            Ok(())
-        }
+    let existed = scene::get(&name).is_some();
+    scene::set(name.clone(), new_scene);
+    // timers activating this scene need new estimates
+    process_timers(&timers, &simple_timers).await;
+    if existed {
+        println!("replaced scene {name:?}");
+        Ok((Code::OK, "scene replaced."))
+    } else {
+        println!("added scene {name:?}");
+        Ok((Code::CREATED, "scene added."))
     }
+}
 
-    // remove duplicates
-    let new_timers = new_timers.into_iter().unique().collect_vec();
-
-    // validate new timers
-    for timer in &new_timers {
-        error_if(*timer.get_timeday().get_hour() > 23, "timeday.hour must be <= 23")?;
-        error_if(*timer.get_timeday().get_minute() > 59, "timeday.minute must be <= 59")?;
-        error_if(timer.get_timeday().get_days().is_empty(), "timeday.days must not be empty")?;
-        error_if(timer.get_timeday().get_days().len() > 7, "timeday.days must have <= 7 elements")?;
-        error_if(timer.get_timeday().get_days().iter().any(|&d| d > 6), "every day in timeday.days has to be <= 6")?;
-        match *timer.get_action() {
-            TimerAction::Sunrise { duration_min, stay_on_for_min, sleep_min, nightlamp_min } => {
-                error_if(duration_min < 1, "action.params.duration_min has to be >= 1")?;
-                error_if(nightlamp_min > 0 && sleep_min < duration_min,
-                    "action.params.sleep_min has to be >= action.params.duration_min if action.params.nightlamp_min is > 0")?;
-                // limit for all: i16::MAX = 32767
-                error_if(sleep_min > 32767, "action.params.sleep_min has to be <= 32767")?;
-                error_if(duration_min > 32767, "action.params.duration_min has to be <= 32767")?;
-                error_if(nightlamp_min > 32767, "action.params.nightlamp_min has to be <= 32767")?;
-                error_if(stay_on_for_min > 32767, "action.params.stay_on_for_min has to be <= 32767")?;
-            },
-            TimerAction::BrightnessState { brightness } => {
-                error_if(brightness < 1, "action.params.brightness has to be >= 1")?;
-                error_if(brightness > 100, "action.params.brightness has to be <= 100")?;
-            },
-            _ => (),
+#[utoipa::path(
+    delete,
+    path = "/scenes/{name}",
+    params(("name" = String, Path, description = "name of the scene")),
+    responses(
+        (status = 200,
+        description = "Successfully deleted the user-defined scene. A built-in scene it overrode is used again."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no user-defined scene with the given name."),
+        (status = 409,
+        description = "Scene is still used by a timer, or is built-in and can only be overridden."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn delete_scene(
+    State((timers, simple_timers)): State<(Timers, SimpleTimers)>,
+    extract::Path(name): extract::Path<String>
+) -> Response<&'static str> {
+    let is_builtin = scene::builtin(&name).is_some();
+    let used = timers.lock().await.iter()
+        .any(|timer| matches!(timer.get_action(), TimerAction::Scene { name: used } if *used == name));
+    if used && !is_builtin {
+        return Err((Code::CONFLICT, ErrorCode::SceneInUse, "scene is still used by a timer").into());
+    }
+    if !scene::remove(&name) {
+        if is_builtin {
+            return Err((Code::CONFLICT, ErrorCode::BuiltInScene, "built-in scenes can't be deleted, only overridden").into());
         }
+        return Err((Code::NOT_FOUND, ErrorCode::SceneNotFound, "there is no scene with this name").into());
     }
-
-    let (timers, simple_timers) = state;
-    *timers.lock().await = new_timers;
     process_timers(&timers, &simple_timers).await;
-    write_timers_to_file(&timers).await;
-    Ok("timers updated.")
+    println!("deleted scene {name:?}");
+    Ok("scene deleted.")
 }
 
-#[derive(Debug, Deserialize, IntoParams, ToSchema)]
-struct PowerState { power: bool }
 #[utoipa::path(
-    put,
-    path = "/power",
-    params(PowerState),
+    post,
+    path = "/scenes/{name}/activate",
+    params(("name" = String, Path, description = "name of the scene")),
     responses(
         (status = 200,
-        description = "Successfully queued requested power state."),
+        description = "Successfully queued all states of the scene.",
+        body = QueuedResponse),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
-        (status = 422,
-        description = "Valid JSON request body had unexpected contents."),
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no scene with the given name."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn put_power(
+async fn post_scene_activate(
     State(function_queue): State<fn_queue::Queue>,
-    extract::Json(powerstate): extract::Json<PowerState>
-) -> Response<&'static str> {
-    let setstate = SetState::Power(powerstate.power);
-    fn_queue::enqueue(&function_queue, Arc::new(move |govee_queue| {
-        govee_queue.push_back(setstate);
-    })).await;
-    println!("queued {setstate:?}");
-    Ok("queued requested state")
+    extract::Path(name): extract::Path<String>
+) -> Response<Json<QueuedResponse>> {
+    if scene::get(&name).is_none() {
+        return Err((Code::NOT_FOUND, ErrorCode::SceneNotFound, "there is no scene with this name").into());
+    }
+    let message = format!("queued scene {name:?} activation");
+    let states = scene::state_amount(&name);
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(move |govee_queue| scene::activate(govee_queue, &name)),
+        states,
+        message
+    ).await)
 }
 
-#[derive(Debug, Deserialize, IntoParams, ToSchema)]
-struct BrightnessState {
-    #[ param(minimum = 1, maximum = 100)]
-    #[schema(minimum = 1, maximum = 100)]
-    brightness: u8
+#[utoipa::path(
+    post,
+    path = "/snapshot",
+    responses(
+        (status = 201,
+        description = "Successfully captured state of lamp after all queued states are set. Only the latest 100 snapshots are kept, and none across restarts.",
+        body = snapshot::Snapshot),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 500,
+        description = "State is not known and fetching it failed, likely because of Govee API rate limit."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_snapshot() -> Response<(Code, Json<snapshot::Snapshot>)> {
+    let expected = fn_queue::expected_state();
+    // fetch what has not been set since the server started
+    let state = match expected.state() {
+        Some(state) => Some(state),
+        None => govee_api::get_state().await.ok().and_then(|fetched| expected.or(&fetched).state())
+    };
+    let Some(state) = state else {
+        return Err((Code::INTERNAL_SERVER_ERROR, ErrorCode::GoveeUnavailable, "could not get state. likely because of Govee API rate limit.").into());
+    };
+    Ok((Code::CREATED, Json(snapshot::take(state))))
 }
+
 #[utoipa::path(
-    put,
-    path = "/brightness",
-    params(BrightnessState),
+    post,
+    path = "/restore/{id}",
+    params(("id" = u32, Path, description = "id of the snapshot")),
     responses(
         (status = 200,
-        description = "Successfully queued requested brightness state."),
+        description = "Successfully queued setting power, brightness and color of the snapshot.",
+        body = QueuedResponse),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
-        (status = 422,
-        description = "Valid JSON request body had unexpected contents."),
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no snapshot with the given id."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn put_brightness(
+async fn post_restore(
     State(function_queue): State<fn_queue::Queue>,
-    extract::Json(brightnessstate): extract::Json<BrightnessState>
-) -> Response<&'static str> {
+    extract::Path(id): extract::Path<u32>
+) -> Response<Json<QueuedResponse>> {
+    let Some(taken) = snapshot::get(id) else {
+        return Err((Code::NOT_FOUND, ErrorCode::SnapshotNotFound, "there is no snapshot with this id").into());
+    };
+    Ok(QueuedResponse::enqueue(
+        &function_queue,
+        Arc::new(move |govee_queue| { snapshot::restore(govee_queue, id); }),
+        snapshot::restore_states(&taken.state).len(),
+        format!("queued restoring snapshot {id}")
+    ).await)
+}
 
-    if brightnessstate.brightness < 1 || brightnessstate.brightness > 100 {
-        return Err((Code::UNPROCESSABLE_ENTITY, "brightness must be from 1 to 100"));
+#[derive(Debug, Deserialize, ToSchema)]
+struct AfterRequest {
+    /// minutes until the action starts
+    #[schema(minimum = 0, maximum = 32767)] // i16::MAX
+    delay_min: u16,
+    action: TimerAction
+}
+#[utoipa::path(
+    post,
+    path = "/after",
+    request_body = AfterRequest,
+    responses(
+        (status = 201,
+        description = "Successfully added countdown running the action once after the delay. Returned the countdown with its id.",
+        body = countdown::Countdown),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 422,
+        description = "Valid JSON request body had unexpected contents."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn post_after(
+    extract::Json(request): extract::Json<AfterRequest>
+) -> Response<(Code, Json<countdown::Countdown>)> {
+    if request.delay_min > 32767 {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidDuration, "delay_min", "delay_min must be <= 32767"));
     }
+    if let Some((field, message)) = validation::action_problems(&request.action).into_iter().next() {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidTimer, field, message));
+    }
+    let countdown = countdown::add(chrono::TimeDelta::minutes(request.delay_min.into()), request.action);
+    println!("added countdown of {} min", request.delay_min);
+    Ok((Code::CREATED, Json(countdown)))
+}
 
-    let setstate = SetState::Brightness(brightnessstate.brightness);
-    fn_queue::enqueue(&function_queue, Arc::new(move |govee_queue| {
-        govee_queue.push_back(setstate);
-    })).await;
-
-    println!("queued {setstate:?}");
-    Ok("queued requested state")
+#[utoipa::path(
+    get,
+    path = "/after",
+    responses(
+        (status = 200,
+        description = "Successfully returned array of pending countdowns.",
+        body = Vec<countdown::Countdown>),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn get_after() -> Response<Json<Vec<countdown::Countdown>>> {
+    Ok(Json(countdown::get_all()))
 }
 
-#[derive(Debug, Deserialize, IntoParams, ToSchema)]
-struct ColorState {
-    #[ param(minimum = 0, maximum = 255)]
-    #[schema(minimum = 0, maximum = 255)]
-    r: u8,
-    #[ param(minimum = 0, maximum = 255)]
-    #[schema(minimum = 0, maximum = 255)]
-    g: u8,
-    #[ param(minimum = 0, maximum = 255)]
-    #[schema(minimum = 0, maximum = 255)]
-    b: u8
+#[utoipa::path(
+    delete,
+    path = "/after/{id}",
+    params(("id" = u32, Path, description = "id of the countdown")),
+    responses(
+        (status = 200,
+        description = "Successfully cancelled countdown. Parts of its action that were queued already are not undone."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no pending countdown with the given id."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn delete_after(
+    extract::Path(id): extract::Path<u32>
+) -> Response<&'static str> {
+    if !countdown::remove(id) {
+        return Err((Code::NOT_FOUND, ErrorCode::CountdownNotFound, "there is no pending countdown with this id").into());
+    }
+    println!("cancelled countdown {id}");
+    Ok("countdown cancelled.")
 }
+
 #[utoipa::path(
-    put,
-    path = "/color",
-    params(ColorState),
+    get,
+    path = "/tokens",
     responses(
         (status = 200,
-        description = "Successfully queued requested color state."),
+        description = "Successfully returned all API tokens without their secrets.",
+        body = Vec<token::TokenInfo>),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn get_tokens() -> Response<Json<Vec<token::TokenInfo>>> {
+    Ok(Json(token::get_all()))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct NewToken {
+    /// 1 to 64 characters: letters, digits, `-` and `_`
+    name: String,
+    /// at least one
+    scopes: Vec<token::Scope>
+}
+#[utoipa::path(
+    post,
+    path = "/tokens",
+    request_body = NewToken,
+    responses(
+        (status = 201,
+        description = "Successfully created API token. Returned its secret, which can't be shown again.",
+        body = token::CreatedToken),
         (status = 400,
         description = "Request did not match expected structure."),
         (status = 401,
-        description = "Bearer authorization token was not sha256 hash of Govee API key."),
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 409,
+        description = "There is a token with this name already."),
         (status = 422,
         description = "Valid JSON request body had unexpected contents."),
     ),
     security(("authorization" = [])) // require auth
 )]
-async fn put_color(
-    State(function_queue): State<fn_queue::Queue>,
-    extract::Json(colorstate): extract::Json<ColorState>
+async fn post_token(
+    extract::Json(new_token): extract::Json<NewToken>
+) -> Response<(Code, Json<token::CreatedToken>)> {
+    data_file::check_name(&new_token.name).map_err(|message| ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidName, "name", message))?;
+    if new_token.scopes.is_empty() {
+        return Err(ApiError::field(Code::UNPROCESSABLE_ENTITY, ErrorCode::InvalidScopes, "scopes", "scopes must not be empty"));
+    }
+    let name = new_token.name.clone();
+    let created = token::create(new_token.name, new_token.scopes)
+        .ok_or_else(|| ApiError::from((Code::CONFLICT, ErrorCode::TokenExists, "there is a token with this name already")))?;
+    println!("created API token {name:?}");
+    Ok((Code::CREATED, Json(created)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/tokens/{name}",
+    params(("name" = String, Path, description = "name of the token")),
+    responses(
+        (status = 200,
+        description = "Successfully revoked API token."),
+        (status = 400,
+        description = "Request did not match expected structure."),
+        (status = 401,
+        description = "Bearer authorization token is unknown or revoked."),
+        (status = 404,
+        description = "There is no token with the given name."),
+    ),
+    security(("authorization" = [])) // require auth
+)]
+async fn delete_token(
+    extract::Path(name): extract::Path<String>
 ) -> Response<&'static str> {
-    let setstate = SetState::Color((colorstate.r, colorstate.g, colorstate.b));
-    fn_queue::enqueue(&function_queue, Arc::new(move |govee_queue| {
-        govee_queue.push_back(setstate);
-    })).await;
-    println!("queued {setstate:?}");
-    Ok("queued requested state")
+    if !token::revoke(&name) {
+        return Err((Code::NOT_FOUND, ErrorCode::TokenNotFound, "there is no token with this name").into());
+    }
+    println!("revoked API token {name:?}");
+    Ok("token revoked.")
+}
+
+/// deprecated routes changing the state of the lamp with GET, replaced by POST routes under /v2
+#[allow(deprecated)]
+fn legacy_routes<S: Clone + Send + Sync + 'static>(function_queue: &fn_queue::Queue) -> axum::Router<S> {
+    use axum::routing::get;
+    use token::Scope::Control;
+    axum::Router::new()
+        .route("/clear_govee_queue", scoped(Control, get(get_clear_govee_queue)))
+        .route("/activate_reminder", scoped(Control, get(get_activate_reminder)))
+        .route("/activate_nightlamp", scoped(Control, get(get_activate_nightlamp)))
+        .route("/activate_daylamp", scoped(Control, get(get_activate_daylamp)))
+        .route("/activate_sunset", scoped(Control, get(get_activate_sunset)))
+            .with_state(Arc::clone(function_queue))
+        .layer(middleware::map_response(deprecation_headers))
+}
+
+/// mark responses of legacy routes as deprecated and link to their successor under /v2
+async fn deprecation_headers(uri: axum::http::Uri, mut response: axum::response::Response) -> axum::response::Response {
+    let headers = response.headers_mut();
+    headers.insert("Deprecation", axum::http::HeaderValue::from_static("true"));
+    if let Ok(link) = format!("</v2{}>; rel=\"successor-version\"", uri.path()).parse() {
+        headers.insert(axum::http::header::LINK, link);
+    }
+    response
 }
 
 /// start webserver. never terminates.
 #[allow(clippy::needless_for_each)] // from inside OpenApi derive
-pub async fn start_server(function_queue: fn_queue::Queue, simple_timers: SimpleTimers) {
-    use constants::net::{LOCALHOST, PORT};
+pub async fn start_server(function_queue: fn_queue::Queue, timers: Timers, simple_timers: SimpleTimers) {
     use utoipa_swagger_ui::SwaggerUi;
-    use tokio::net::TcpListener;
-    use axum::{response::Redirect, routing::{get, put}};
+    use api_error::ErrorDoc;
+    use axum::{response::Redirect, routing::{get, put, post, delete}};
     use utoipa::{OpenApi, openapi::security::{SecurityScheme, Http, HttpAuthScheme}};
 
     /// utility struct for utoipa to register bearer http authorization.
@@ -383,26 +1894,77 @@ pub async fn start_server(function_queue: fn_queue::Queue, simple_timers: Simple
                     SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer))
                 );
             }
+            // every route requiring authorization also requires a scope and may be locked out
+            for item in openapi.paths.paths.values_mut() {
+                let operations = [&mut item.get, &mut item.put, &mut item.post, &mut item.delete, &mut item.patch];
+                for operation in operations.into_iter().flatten().filter(|operation| operation.security.is_some()) {
+                    operation.responses.responses.entry(String::from("403")).or_insert_with(|| utoipa::openapi::Response::new(
+                        "Bearer authorization token does not have the scope required for this route: `read` for GET routes and validating timers, `timers:write` for changing timers, programs, scenes and countdowns, `admin` for tokens, `control` otherwise."
+                    ).into());
+                    operation.responses.responses.entry(String::from("429")).or_insert_with(|| utoipa::openapi::Response::new(
+                        "Too many failed authorizations from this address, it is locked out for the time in the `Retry-After` header."
+                    ).into());
+                }
+            }
         }
     }
 
     // set up utoipa swagger ui
     #[derive(OpenApi)]
     #[openapi(
-        // use security scheme for basic http authorization
-        modifiers(&AuthHint),
+        // use security scheme for basic http authorization, document error responses
+        modifiers(&AuthHint, &ErrorDoc),
         paths(
             // functions with #[utoipa::path(...)]
             get_state,
+            post_v2_clear_govee_queue,
             get_clear_govee_queue,
             put_power,
             put_brightness,
             put_color,
+            put_color_temperature,
             get_timers,
             put_timers,
+            post_timers_validate,
+            post_timer,
+            get_timer,
+            put_timer,
+            patch_timer,
+            delete_timer,
+            post_timer_enable,
+            post_timer_disable,
+            get_suppressed_timers,
+            get_timer_schedule,
+            post_timer_skip_next,
+            delete_timer_skip_next,
+            put_timer_pause,
+            put_snooze,
+            post_v2_activate_reminder,
+            post_v2_activate_nightlamp,
+            post_v2_activate_daylamp,
+            post_v2_activate_sunset,
             get_activate_reminder,
             get_activate_nightlamp,
             get_activate_daylamp,
+            get_activate_sunset,
+            get_programs,
+            get_program,
+            put_program,
+            delete_program,
+            post_program_run,
+            get_scenes,
+            get_scene,
+            put_scene,
+            delete_scene,
+            post_scene_activate,
+            post_snapshot,
+            post_restore,
+            post_after,
+            get_after,
+            delete_after,
+            get_tokens,
+            post_token,
+            delete_token,
         ),
         components(schemas(
             // enums/structs with #[derive(utoipa::ToSchema)]
@@ -410,47 +1972,149 @@ pub async fn start_server(function_queue: fn_queue::Queue, simple_timers: Simple
             PowerState,
             BrightnessState,
             ColorState,
+            ColorTemperatureState,
+            PauseState,
+            SnoozeState,
+            SuppressedOccurrence,
+            QueuedResponse,
+            api_error::ErrorBody,
+            token::Scope,
+            token::TokenInfo,
+            token::CreatedToken,
+            NewToken,
+            api_error::ErrorDetails,
+            api_error::ErrorCode,
+            snapshot::Snapshot,
+            countdown::Countdown,
+            AfterRequest,
+            reminder::ReminderAfterwards,
+            ScheduledEvent,
+            validation::ValidationReport,
+            validation::ValidationError,
+            validation::ValidationWarning,
+            program::Program,
+            program::Keyframe,
+            scene::Scene,
             Timer
         ))
     )]
     struct ApiDoc;
 
-    // higher level timers which will be converted and pushed to `simple_timers`
-    let timers: Timers = load_timers(&simple_timers).await;
+    let snooze_task: SnoozeTask = Arc::new(tokio::sync::Mutex::new(None));
+    let timers_write_lock: TimersWriteLock = Arc::new(tokio::sync::Mutex::new(()));
+
+    // every API route declares the scope it requires, see `scoped`
+    use token::Scope::{Admin, Control, Read, TimersWrite};
+
+    // routes reading and changing timers, with optimistic concurrency.
+    // the version is checked after authorization, so only known tokens learn it.
+    let versioned = middleware::from_fn_with_state((Arc::clone(&timers), Arc::clone(&timers_write_lock)), check_timers_version);
+    let timer_routes = axum::Router::new()
+        .route("/timers", scoped(Read, get(get_timers).route_layer(versioned.clone())))
+            .with_state(Arc::clone(&timers))
+        .route("/timers", scoped(TimersWrite, put(put_timers).post(post_timer).route_layer(versioned.clone())))
+            .with_state((Arc::clone(&timers), Arc::clone(&simple_timers)))
+        .route("/timers/{id}", scoped(Read, get(get_timer).route_layer(versioned.clone())))
+            .with_state(Arc::clone(&timers))
+        .route("/timers/{id}", scoped(TimersWrite, put(put_timer).patch(patch_timer).delete(delete_timer).route_layer(versioned.clone())))
+            .with_state((Arc::clone(&timers), Arc::clone(&simple_timers)))
+        .route("/timers/{id}/enable", scoped(TimersWrite, post(post_timer_enable).route_layer(versioned.clone())))
+            .with_state((Arc::clone(&timers), Arc::clone(&simple_timers)))
+        .route("/timers/{id}/disable", scoped(TimersWrite, post(post_timer_disable).route_layer(versioned.clone())))
+            .with_state((Arc::clone(&timers), Arc::clone(&simple_timers)))
+        .route("/timers/{id}/skip_next", scoped(TimersWrite, post(post_timer_skip_next).delete(delete_timer_skip_next).route_layer(versioned.clone())))
+            .with_state((Arc::clone(&timers), Arc::clone(&simple_timers)))
+        .route("/timers/{id}/pause", scoped(TimersWrite, put(put_timer_pause).route_layer(versioned)))
+            .with_state((Arc::clone(&timers), Arc::clone(&simple_timers)));
+
 
     // configure routes
     let app = axum::Router::new()
         // api routes
-        .route("/state", get(get_state))
-        .route("/clear_govee_queue", get(get_clear_govee_queue))
+        .route("/state", scoped(Read, get(get_state)))
+        .route("/v2/clear_govee_queue", scoped(Control, post(post_v2_clear_govee_queue)))
+            .with_state(Arc::clone(&function_queue))
+        .route("/v2/activate_reminder", scoped(Control, post(post_v2_activate_reminder)))
             .with_state(Arc::clone(&function_queue))
-        .route("/activate_reminder", get(get_activate_reminder))
+        .route("/v2/activate_nightlamp", scoped(Control, post(post_v2_activate_nightlamp)))
             .with_state(Arc::clone(&function_queue))
-        .route("/activate_nightlamp", get(get_activate_nightlamp))
+        .route("/v2/activate_daylamp", scoped(Control, post(post_v2_activate_daylamp)))
             .with_state(Arc::clone(&function_queue))
-        .route("/activate_daylamp", get(get_activate_daylamp))
+        .route("/v2/activate_sunset", scoped(Control, post(post_v2_activate_sunset)))
             .with_state(Arc::clone(&function_queue))
-        .route("/power", put(put_power))
+        .route("/power", scoped(Control, put(put_power)))
             .with_state(Arc::clone(&function_queue))
-        .route("/brightness", put(put_brightness))
+        .route("/brightness", scoped(Control, put(put_brightness)))
             .with_state(Arc::clone(&function_queue))
-        .route("/color", put(put_color))
+        .route("/color", scoped(Control, put(put_color)))
             .with_state(Arc::clone(&function_queue))
-        .route("/timers", get(get_timers))
+        .route("/color_temperature", scoped(Control, put(put_color_temperature)))
+            .with_state(Arc::clone(&function_queue))
+        .merge(if config::legacy_get_routes() { legacy_routes(&function_queue) } else { axum::Router::new() })
+        .merge(timer_routes)
+        .route("/timers/validate", scoped(Read, post(post_timers_validate)))
+        .route("/timers/suppressed", scoped(Read, get(get_suppressed_timers)))
+            .with_state(Arc::clone(&timers))
+        .route("/timers/schedule", scoped(Read, get(get_timer_schedule)))
             .with_state(Arc::clone(&timers))
-        .route("/timers", put(put_timers))
+        .route("/snooze", scoped(Control, put(put_snooze)))
+            .with_state((Arc::clone(&timers), Arc::clone(&simple_timers), Arc::clone(&function_queue), Arc::clone(&snooze_task), Arc::clone(&timers_write_lock)))
+        .route("/programs", scoped(Read, get(get_programs)))
+        .route("/programs/{name}", scoped(Read, get(get_program)))
+        .route("/programs/{name}", scoped(TimersWrite, put(put_program)))
             .with_state((Arc::clone(&timers), Arc::clone(&simple_timers)))
-
-        // require authorization for the routes above with middleware
-        .route_layer(middleware::from_fn(validate_request))
+        .route("/programs/{name}", scoped(TimersWrite, delete(delete_program)))
+            .with_state(Arc::clone(&timers))
+        .route("/programs/{name}/run", scoped(Control, post(post_program_run)))
+            .with_state(Arc::clone(&function_queue))
+        .route("/scenes", scoped(Read, get(get_scenes)))
+        .route("/scenes/{name}", scoped(Read, get(get_scene)))
+        .route("/scenes/{name}", scoped(TimersWrite, put(put_scene).delete(delete_scene)))
+            .with_state((Arc::clone(&timers), Arc::clone(&simple_timers)))
+        .route("/scenes/{name}/activate", scoped(Control, post(post_scene_activate)))
+            .with_state(Arc::clone(&function_queue))
+        .route("/snapshot", scoped(Control, post(post_snapshot)))
+        .route("/restore/{id}", scoped(Control, post(post_restore)))
+            .with_state(Arc::clone(&function_queue))
+        .route("/after", scoped(Read, get(get_after)).merge(scoped(TimersWrite, post(post_after))))
+        .route("/after/{id}", scoped(TimersWrite, delete(delete_after)))
+        .route("/tokens", scoped(Admin, get(get_tokens).post(post_token)))
+        .route("/tokens/{name}", scoped(Admin, delete(delete_token)))
 
         // temporarily redirect root to swagger ui
         .route("/", get(|| async { Redirect::temporary("/swagger-ui") }))
         // swagger ui
         .merge(SwaggerUi::new("/swagger-ui")
-            .url("/openapi.json", ApiDoc::openapi()));
+            .url("/openapi.json", ApiDoc::openapi()))
+
+        // give every request an id and respond to all errors with JSON
+        .layer(middleware::from_fn(api_error::request_id));
+
+    // panic at start instead of serving without HTTPS
+    let acceptor = config::tls().map(|files| {
+        let acceptor = Arc::new(std::sync::RwLock::new(files.acceptor().unwrap_or_else(|message| panic!(
+            "TLS in config file could not be set up: {message}\n"
+        ))));
+        listener::reload_on_change(files.clone(), Arc::clone(&acceptor));
+        acceptor
+    });
 
-    let address = std::net::SocketAddr::new(LOCALHOST, PORT);
-    println!("WEB: starting server on http://{address} ...");
-    axum::serve(TcpListener::bind(address).await.unwrap(), app).await.unwrap();
+    // serve on every address until the server stops
+    let mut servers = tokio::task::JoinSet::new();
+    for address in config::listen() {
+        let tcp = listener::bind(address).unwrap_or_else(|error| panic!("could not listen on {address}: {error}\n"));
+        // source addresses are needed to lock out clients failing to authorize
+        let service = app.clone().into_make_service_with_connect_info::<Client>();
+        if let Some(acceptor) = &acceptor {
+            println!("WEB: starting server on https://{address} ...");
+            let tls = listener::TlsListener::new(tcp, Arc::clone(acceptor)).unwrap();
+            servers.spawn(async move { axum::serve(tls, service).await.unwrap() });
+        } else {
+            println!("WEB: starting server on http://{address} ...");
+            servers.spawn(async move { axum::serve(tcp, service).await.unwrap() });
+        }
+    }
+    while let Some(result) = servers.join_next().await {
+        result.unwrap();
+    }
 }
\ No newline at end of file
@@ -4,6 +4,34 @@ mod constants;
 
 #[tokio::main]
 async fn main() {
+    // `lamp-server self-signed-cert [HOST...]` generates a certificate instead of running the server
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|command| command == "self-signed-cert") {
+        match util::listener::generate_self_signed(&args[1..]) {
+            Ok(files) => println!(
+                "generated self-signed certificate. add to your config file to serve HTTPS:\ntls:\n  cert: {:?}\n  key: {:?}",
+                files.cert, files.key
+            ),
+            Err(message) => {
+                eprintln!("{message}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `lamp-server token create --scope admin NAME` creates a token, e.g. the first admin token, and prints it once
+    if args.first().is_some_and(|command| command == "token") {
+        match control::token::create_from_args(&args[1..]) {
+            Ok(created) => println!("{}", serde_json::to_string_pretty(&created).unwrap()),
+            Err(message) => {
+                eprintln!("{message}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     // await async main loop (never terminates)
     control::main_loop().await;
 }
\ No newline at end of file
@@ -0,0 +1,149 @@
+use chrono::{NaiveDate, Days};
+
+/// inclusive range of dates
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct DateRange {
+    pub from: NaiveDate,
+    pub to: NaiveDate
+}
+
+/// set of dates like public holidays or vacations, defined in the config file
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct Calendar {
+    /// single dates
+    #[serde(default)]
+    dates: Vec<NaiveDate>,
+    /// inclusive ranges of dates
+    #[serde(default)]
+    ranges: Vec<DateRange>,
+    /// path to local `.ics` file. all days covered by its events are part of the calendar.
+    #[serde(default)]
+    ics_file: Option<String>,
+    /// ranges parsed from `ics_file`
+    #[serde(skip)]
+    ics_ranges: Vec<DateRange>
+}
+
+impl Calendar {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.dates.contains(&date)
+        || self.ranges.iter()
+            .chain(self.ics_ranges.iter())
+            .any(|range| range.from <= date && date <= range.to)
+    }
+
+    /// read and parse `ics_file` (if given). return error message on failure.
+    pub fn load_ics_file(&mut self) -> Result<(), String> {
+        let Some(path) = &self.ics_file else {
+            return Ok(());
+        };
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {path}: {e}"))?;
+        self.ics_ranges = parse_ics(&content)?;
+        println!("SETUP: loaded {} event(s) from {path}", self.ics_ranges.len());
+        Ok(())
+    }
+}
+
+/// dates covered by the events (`VEVENT`) of an iCalendar file.
+/// recurrence rules are not supported, only the first occurrence of an event is used.
+pub fn parse_ics(content: &str) -> Result<Vec<DateRange>, String> {
+    let mut ranges = vec![];
+    let mut start: Option<(NaiveDate, bool)> = None;
+    let mut end: Option<(NaiveDate, bool)> = None;
+
+    // lines starting with whitespace continue the previous line
+    let unfolded = content.replace("\r\n", "\n").replace("\n ", "").replace("\n\t", "");
+
+    for line in unfolded.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // ignore parameters like in "DTSTART;VALUE=DATE:20240101"
+        let name = name.split(';').next().unwrap_or_default();
+        match name {
+            "BEGIN" if value == "VEVENT" => {
+                start = None;
+                end = None;
+            },
+            "DTSTART" => start = Some(parse_ics_date(value)?),
+            "DTEND" => end = Some(parse_ics_date(value)?),
+            "END" if value == "VEVENT" => {
+                let Some((from, _)) = start else {
+                    return Err(String::from("event without DTSTART"));
+                };
+                let to = match end {
+                    // end of all-day events is exclusive
+                    Some((to, true)) if to > from => to - Days::new(1),
+                    Some((to, _)) => to.max(from),
+                    None => from
+                };
+                ranges.push(DateRange { from, to });
+            },
+            _ => ()
+        }
+    }
+
+    Ok(ranges)
+}
+
+/// parse value like "20240101" or "20240101T120000Z".
+/// also return whether it was a date without time.
+fn parse_ics_date(value: &str) -> Result<(NaiveDate, bool), String> {
+    let date = value.get(0..8).unwrap_or(value);
+    NaiveDate::parse_from_str(date, "%Y%m%d")
+        .map(|date| (date, value.len() == 8))
+        .map_err(|_| format!("invalid date {value:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn ics_all_day_events() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+            BEGIN:VEVENT\r\n\
+            SUMMARY:Christmas\r\n\
+            DTSTART;VALUE=DATE:20241224\r\n\
+            DTEND;VALUE=DATE:20241227\r\n\
+            END:VEVENT\r\n\
+            BEGIN:VEVENT\r\n\
+            SUMMARY:New Year\r\n\
+            DTSTART;VALUE=DATE:20250101\r\n\
+            END:VEVENT\r\n\
+            END:VCALENDAR\r\n";
+        let ranges = parse_ics(ics).unwrap();
+        assert_eq!(ranges, vec![
+            DateRange { from: date(2024, 12, 24), to: date(2024, 12, 26) },
+            DateRange { from: date(2025, 1, 1), to: date(2025, 1, 1) },
+        ]);
+    }
+
+    #[test]
+    fn ics_timed_event() {
+        let ics = "BEGIN:VEVENT\n\
+            DTSTART:20240301T080000Z\n\
+            DTEND:20240302T\n 120000Z\n\
+            END:VEVENT\n";
+        let ranges = parse_ics(ics).unwrap();
+        assert_eq!(ranges, vec![DateRange { from: date(2024, 3, 1), to: date(2024, 3, 2) }]);
+    }
+
+    #[test]
+    fn calendar_contains() {
+        let calendar = Calendar {
+            dates: vec![date(2024, 5, 1)],
+            ranges: vec![DateRange { from: date(2024, 8, 1), to: date(2024, 8, 14) }],
+            ..Default::default()
+        };
+        assert!(calendar.contains(date(2024, 5, 1)));
+        assert!(calendar.contains(date(2024, 8, 14)));
+        assert!(!calendar.contains(date(2024, 8, 15)));
+        assert!(!calendar.contains(date(2024, 5, 2)));
+    }
+}
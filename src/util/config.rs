@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::OnceLock; // thread-safe and can only be written to once
+use crate::constants;
+use crate::util::{calendar::Calendar, reminder::ReminderPattern, solar::Location, listener::TlsFiles};
+
+// global instance which will receive values from config file
+pub static INSTANCE: OnceLock<Struct> = OnceLock::new();
+
+/// calendar with given name from config file (if defined and config has been loaded)
+pub fn calendar(name: &str) -> Option<&'static Calendar> {
+    INSTANCE.get()?.calendars.get(name)
+}
+
+/// location for solar timers (if configured and config has been loaded)
+pub fn location() -> Option<&'static Location> {
+    INSTANCE.get()?.location.as_ref()
+}
+
+/// color temperature in Kelvin to use for the nightlamp instead of its RGB color (if configured)
+pub fn nightlamp_color_temperature() -> Option<u16> {
+    INSTANCE.get()?.nightlamp_color_temperature
+}
+
+/// color temperature in Kelvin to use for the daylamp instead of its RGB color (if configured)
+pub fn daylamp_color_temperature() -> Option<u16> {
+    INSTANCE.get()?.daylamp_color_temperature
+}
+
+/// whether the lamp supports setting a color temperature natively, defaults to true
+pub fn native_color_temperature() -> bool {
+    INSTANCE.get().and_then(|config| config.native_color_temperature).unwrap_or(true)
+}
+
+/// whether the deprecated GET routes changing the lamp state are available, defaults to true
+pub fn legacy_get_routes() -> bool {
+    INSTANCE.get().and_then(|config| config.legacy_get_routes).unwrap_or(true)
+}
+
+/// whether the sha256 hash of the Govee API key can be used as API token with every scope, defaults to true
+pub fn legacy_token() -> bool {
+    INSTANCE.get().and_then(|config| config.legacy_token).unwrap_or(true)
+}
+
+/// addresses to serve on, defaults to localhost
+pub fn listen() -> Vec<std::net::SocketAddr> {
+    match INSTANCE.get() {
+        Some(config) if !config.listen.is_empty() => config.listen.clone(),
+        _ => vec![std::net::SocketAddr::new(constants::net::LOCALHOST, constants::net::PORT)]
+    }
+}
+
+/// addresses of reverse proxies whose `X-Forwarded-For` header is used as source address, defaults to none
+pub fn trusted_proxies() -> &'static [std::net::IpAddr] {
+    INSTANCE.get().map_or(&[], |config| config.trusted_proxies.as_slice())
+}
+
+/// certificate and key to serve HTTPS with instead of HTTP (if configured)
+pub fn tls() -> Option<&'static TlsFiles> {
+    INSTANCE.get()?.tls.as_ref()
+}
+
+/// print a warning for each color temperature in the config file the lamp doesn't support.
+/// the range might only be estimated, so the server starts anyway.
+pub fn check_color_temperatures() {
+    let range = crate::util::govee_api::color_temperature_range();
+    for kelvin in [nightlamp_color_temperature(), daylamp_color_temperature()].into_iter().flatten() {
+        if !range.contains(&kelvin) {
+            println!("SETUP: color temperature {kelvin} in config file is out of range {range:?} of the lamp, it will be limited to it");
+        }
+    }
+}
+
+/// default reminder pattern from config file
+pub fn reminder_pattern() -> ReminderPattern {
+    INSTANCE.get().map(|config| config.reminder).unwrap_or_default()
+}
+
+/// optional settings besides govee secrets. see [`crate::util::govee_secrets`] for those.
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct Struct {
+    /// named calendars of dates that timers can reference
+    #[serde(default)]
+    pub calendars: HashMap<String, Calendar>,
+    /// location for timers relative to the sun
+    #[serde(default)]
+    pub location: Option<Location>,
+    /// nightlamp preset in Kelvin instead of RGB
+    #[serde(default)]
+    pub nightlamp_color_temperature: Option<u16>,
+    /// daylamp preset in Kelvin instead of RGB
+    #[serde(default)]
+    pub daylamp_color_temperature: Option<u16>,
+    /// set to false if the lamp model doesn't support the colorTem command,
+    /// to use approximated RGB colors instead
+    #[serde(default)]
+    pub native_color_temperature: Option<bool>,
+    /// how reminders flash by default
+    #[serde(default)]
+    pub reminder: ReminderPattern,
+    /// whether `GET /clear_govee_queue` and `GET /activate_*` are available besides their POST routes under /v2
+    #[serde(default)]
+    pub legacy_get_routes: Option<bool>,
+    /// whether the sha256 hash of the Govee API key can be used as API token besides the tokens of `/tokens`
+    #[serde(default)]
+    pub legacy_token: Option<bool>,
+    /// addresses with port to serve on, like "0.0.0.0:9000" or "[::]:9000"
+    #[serde(default)]
+    pub listen: Vec<std::net::SocketAddr>,
+    /// serve HTTPS on all addresses
+    #[serde(default)]
+    pub tls: Option<TlsFiles>,
+    /// reverse proxies in front of the server, like "127.0.0.1"
+    #[serde(default)]
+    pub trusted_proxies: Vec<std::net::IpAddr>,
+}
+
+/// path and contents of config file.
+/// panics with error messages if config file can't be read.
+pub fn read_file() -> (String, String) {
+    let mut path = dirs_next::config_dir()
+        .expect("path to config file could not be determined, which means your operating system is not supported.\n");
+    path.push(constants::CONFIG_FILE_NAME);
+    let path = path.to_str().unwrap().to_owned();
+
+    // read file contents
+    let content = std::fs::read_to_string(&path).unwrap_or_else( |_| panic!(
+        "config file could not be read from {path}.\nsee the README for a template.\n"
+    ));
+
+    (path, content)
+}
+
+/// panics with error messages if valid `Struct` can't be retrieved
+pub fn from_file() -> Struct {
+    let (path, yaml_config) = read_file();
+
+    // parse yaml string to struct
+    let mut config: Struct = serde_yaml::from_str(&yaml_config).unwrap_or_else(|_| panic!(
+        "config file at {path} could not be parsed.\nsee the README for a template.\n"
+    ));
+
+    config.reminder.validate().unwrap_or_else(|message| panic!(
+        "reminder in config file at {path} is invalid: {message}\n"
+    ));
+
+    for (name, calendar) in &mut config.calendars {
+        calendar.load_ics_file().unwrap_or_else(|message| panic!(
+            "ics file of calendar {name} could not be loaded: {message}\n"
+        ));
+    }
+
+    config
+}
@@ -0,0 +1,210 @@
+use itertools::Itertools;
+use chrono::{Datelike, Days, NaiveDateTime, NaiveTime, Timelike};
+
+/// parsed cron-style schedule with minute precision.
+/// fields are `minute hour day-of-month month day-of-week`,
+/// optionally preceded by a seconds field which is ignored. supports
+/// `*`, values, ranges (`1-5`), steps (`*/2`, `9-17/2`), lists (`1,3`),
+/// names of months and weekdays (`JAN`, `MON`) and the nth weekday of the month (`MON#1`).
+/// like in classic cron, a date matches if day-of-month or day-of-week matches when both are restricted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    expression: String,
+    minutes: Vec<u8>,
+    hours: Vec<u8>,
+    days_of_month: Vec<u8>,
+    months: Vec<u8>,
+    /// weekday from 0 (sunday) to 6 and optional occurrence in the month from 1 to 5
+    days_of_week: Vec<(u8, Option<u8>)>,
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool
+}
+
+const MONTH_NAMES: [&str; 12] = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+const WEEKDAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+impl Cron {
+    /// return error message if `expression` is invalid
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields = expression.split_whitespace().collect_vec();
+        let fields = match fields.len() {
+            5 => &fields[..],
+            // ignore seconds
+            6 => &fields[1..],
+            n => return Err(format!("expected 5 or 6 fields, got {n}"))
+        };
+
+        let days_of_week = fields[4].split(',')
+            .map(|part| match part.split_once('#') {
+                Some((day, nth)) => {
+                    let day = parse_value(day, 0, 7, &WEEKDAY_NAMES, 0)?;
+                    let nth = nth.parse::<u8>().ok().filter(|nth| (1 ..= 5).contains(nth))
+                        .ok_or_else(|| format!("invalid occurrence in month {nth:?}, expected 1 to 5"))?;
+                    Ok(vec![(day % 7, Some(nth))])
+                },
+                None => Ok(parse_part(part, 0, 7, &WEEKDAY_NAMES, 0)?.into_iter()
+                    // 7 is also sunday
+                    .map(|day| (day % 7, None))
+                    .collect_vec())
+            })
+            .flatten_ok()
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            expression: expression.to_owned(),
+            minutes: parse_field(fields[0], 0, 59, &[], 0)?,
+            hours: parse_field(fields[1], 0, 23, &[], 0)?,
+            days_of_month: parse_field(fields[2], 1, 31, &[], 0)?,
+            months: parse_field(fields[3], 1, 12, &MONTH_NAMES, 1)?,
+            days_of_week: days_of_week.into_iter().unique().collect(),
+            days_of_month_restricted: fields[2] != "*",
+            days_of_week_restricted: fields[4] != "*"
+        })
+    }
+
+    /// whether given date/time matches, ignoring seconds
+    pub fn matches(&self, datetime: &NaiveDateTime) -> bool {
+        self.matches_date(datetime)
+        && self.hours.contains(&u8::try_from(datetime.hour()).unwrap())
+        && self.minutes.contains(&u8::try_from(datetime.minute()).unwrap())
+    }
+
+    fn matches_date(&self, datetime: &NaiveDateTime) -> bool {
+        let month = u8::try_from(datetime.month()).unwrap();
+        let day = u8::try_from(datetime.day()).unwrap();
+        let weekday = u8::try_from(datetime.weekday().num_days_from_sunday()).unwrap();
+        // occurrence of the weekday in the month, from 1
+        let nth = (day - 1) / 7 + 1;
+
+        let day_of_month = self.days_of_month.contains(&day);
+        let day_of_week = self.days_of_week.iter()
+            .any(|&(d, n)| d == weekday && n.is_none_or(|n| n == nth));
+
+        self.months.contains(&month) && match (self.days_of_month_restricted, self.days_of_week_restricted) {
+            (true, true) => day_of_month || day_of_week,
+            (true, false) => day_of_month,
+            (false, _) => day_of_week
+        }
+    }
+
+    /// earliest date/time (with seconds set to 0) strictly after `after`.
+    /// none if there is none within the next 5 years.
+    pub fn next_after(&self, after: &NaiveDateTime) -> Option<NaiveDateTime> {
+        after.date().iter_days()
+            .take_while(|date| *date <= after.date() + Days::new(5 * 366))
+            .filter(|date| self.matches_date(&date.and_time(NaiveTime::MIN)))
+            .flat_map(|date| self.hours.iter()
+                .cartesian_product(self.minutes.iter())
+                .map(move |(&hour, &minute)| date.and_hms_opt(hour.into(), minute.into(), 0).unwrap()))
+            .find(|datetime| datetime > after)
+    }
+}
+
+/// parse comma separated list of parts. return sorted values without duplicates.
+fn parse_field(field: &str, min: u8, max: u8, names: &[&str], first_name: u8) -> Result<Vec<u8>, String> {
+    field.split(',')
+        .map(|part| parse_part(part, min, max, names, first_name))
+        .flatten_ok()
+        .collect::<Result<Vec<_>, String>>()
+        .map(|values| values.into_iter().unique().sorted().collect())
+}
+
+/// parse part like `*`, `5`, `1-5`, `*/2` or `1-5/2`
+fn parse_part(part: &str, min: u8, max: u8, names: &[&str], first_name: u8) -> Result<Vec<u8>, String> {
+    let (range, step) = match part.split_once('/') {
+        Some((range, step)) => (range, step.parse::<u8>().ok().filter(|&s| s > 0)
+            .ok_or_else(|| format!("invalid step {step:?}"))?),
+        None => (part, 1)
+    };
+    let (start, stop) = match range.split_once('-') {
+        _ if range == "*" => (min, max),
+        Some((start, stop)) => (
+            parse_value(start, min, max, names, first_name)?,
+            parse_value(stop, min, max, names, first_name)?
+        ),
+        // single value with step means until max
+        None if part.contains('/') => (parse_value(range, min, max, names, first_name)?, max),
+        None => {
+            let value = parse_value(range, min, max, names, first_name)?;
+            (value, value)
+        }
+    };
+    if start > stop {
+        return Err(format!("range {range:?} has to be ascending"));
+    }
+    Ok((start ..= stop).step_by(step.into()).collect())
+}
+
+/// parse number or (case insensitive) name from `min` to `max`.
+/// `names` belong to values starting from `first_name`.
+fn parse_value(value: &str, min: u8, max: u8, names: &[&str], first_name: u8) -> Result<u8, String> {
+    let parsed = names.iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+        .map(|index| u8::try_from(index).unwrap() + first_name)
+        .or_else(|| value.parse::<u8>().ok());
+    parsed.filter(|v| (min ..= max).contains(v))
+        .ok_or_else(|| format!("invalid value {value:?}, expected {min} to {max}"))
+}
+
+impl std::fmt::Display for Cron {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn datetime(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn every_two_hours_on_workdays() {
+        let cron = Cron::parse("0 9-17/2 * * MON-FRI").unwrap();
+        // monday
+        assert!(cron.matches(&datetime(2024, 1, 1, 9, 0)));
+        assert!(cron.matches(&datetime(2024, 1, 1, 17, 0)));
+        assert!(!cron.matches(&datetime(2024, 1, 1, 10, 0)));
+        assert!(!cron.matches(&datetime(2024, 1, 1, 9, 1)));
+        // saturday
+        assert!(!cron.matches(&datetime(2024, 1, 6, 9, 0)));
+    }
+
+    #[test]
+    fn first_monday_of_month() {
+        let cron = Cron::parse("30 7 * * MON#1").unwrap();
+        assert!(cron.matches(&datetime(2024, 1, 1, 7, 30)));
+        assert!(!cron.matches(&datetime(2024, 1, 8, 7, 30)));
+        assert_eq!(cron.next_after(&datetime(2024, 1, 1, 7, 30)), Some(datetime(2024, 2, 5, 7, 30)));
+    }
+
+    #[test]
+    fn day_of_month_or_day_of_week() {
+        let cron = Cron::parse("0 0 13 * 5").unwrap();
+        // friday
+        assert!(cron.matches(&datetime(2024, 1, 5, 0, 0)));
+        // 13th, saturday
+        assert!(cron.matches(&datetime(2024, 1, 13, 0, 0)));
+        assert!(!cron.matches(&datetime(2024, 1, 14, 0, 0)));
+    }
+
+    #[test]
+    fn seconds_are_ignored() {
+        let cron = Cron::parse("*/10 15 8 * * *").unwrap();
+        assert!(cron.matches(&datetime(2024, 3, 3, 8, 15)));
+        assert_eq!(cron.next_after(&datetime(2024, 3, 3, 8, 15)), Some(datetime(2024, 3, 4, 8, 15)));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(Cron::parse("* * * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("* * 0 * *").is_err());
+        assert!(Cron::parse("* * * * MON#6").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+        assert!(Cron::parse("5-1 * * * *").is_err());
+    }
+}
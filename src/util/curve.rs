@@ -0,0 +1,77 @@
+/// easing function mapping progress from 0 to 1 onto a value from 0 to 1
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// quadratic, starts slow and ends fast
+    EaseIn,
+    /// steps that are perceived as equally large, using the CIE 1976 lightness.
+    /// starts very slow, similar to a logarithmic dimmer.
+    #[serde(alias = "Logarithmic")]
+    Perceptual,
+    /// progress to the power of the given value (1 to 50) divided by 10, e.g. 22 for a gamma of 2.2
+    Gamma(u8)
+}
+
+impl Curve {
+    /// value at `progress` (clamped to 0 to 1). 0 at 0 and 1 at 1.
+    pub fn apply(self, progress: f64) -> f64 {
+        let progress = progress.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => progress,
+            Self::EaseIn => progress * progress,
+            Self::Perceptual => {
+                // inverse of the CIE 1976 lightness from 0 to 100
+                let lightness = progress * 100.0;
+                if lightness > 8.0 {
+                    ((lightness + 16.0) / 116.0).powi(3)
+                } else {
+                    lightness / 903.3
+                }
+            },
+            Self::Gamma(gamma) => progress.powf(f64::from(gamma) / 10.0)
+        }
+    }
+
+    /// value from `start` to `stop` at `progress` (clamped to 0 to 1)
+    pub fn interpolate(self, start: f64, stop: f64, progress: f64) -> f64 {
+        (stop - start).mul_add(self.apply(progress), start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [Curve; 5] = [Curve::Linear, Curve::EaseIn, Curve::Perceptual, Curve::Gamma(22), Curve::Gamma(5)];
+
+    #[test]
+    fn endpoints() {
+        for curve in CURVES {
+            assert!(curve.apply(0.0).abs() < 1e-9, "{curve:?} does not start at 0");
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-9, "{curve:?} does not stop at 1");
+        }
+    }
+
+    #[test]
+    fn monotonic() {
+        for curve in CURVES {
+            let values = (0 ..= 1000).map(|i| curve.apply(f64::from(i) / 1000.0)).collect::<Vec<_>>();
+            assert!(values.windows(2).all(|pair| pair[0] < pair[1]), "{curve:?} is not strictly increasing");
+        }
+    }
+
+    #[test]
+    fn slow_start() {
+        // perceived brightness is not linear, so these should start slower
+        for curve in [Curve::EaseIn, Curve::Perceptual, Curve::Gamma(22)] {
+            assert!(curve.apply(0.25) < Curve::Linear.apply(0.25), "{curve:?} does not start slower than linear");
+        }
+    }
+
+    #[test]
+    fn interpolate_descending() {
+        assert!((Curve::Linear.interpolate(0.8, 0.55, 0.5) - 0.675).abs() < 1e-9);
+        assert!((Curve::EaseIn.interpolate(100.0, 0.0, 1.0)).abs() < 1e-9);
+    }
+}
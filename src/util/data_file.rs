@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+/// path to file `name` in [`dirs_next::data_dir()`]
+fn path(name: &str) -> Option<PathBuf> {
+    let mut path = dirs_next::data_dir()?;
+    path.push(name);
+    Some(path)
+}
+
+/// read and parse json file `name` in the data dir. return error message on failure.
+pub fn read<T: serde::de::DeserializeOwned>(name: &str) -> Result<T, &'static str> {
+    let Some(path) = path(name) else {
+        return Err("couldn't get path to data dir");
+    };
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Err("file doesn't exist");
+    };
+    serde_json::from_str(&content).map_err(|_| "couldn't parse existing file")
+}
+
+/// serialize `value` as json and write it to file `name` in the data dir.
+/// ignores failures.
+pub fn write<T: serde::Serialize>(name: &str, value: &T) {
+    // ignore if path can't be determined
+    let Some(path) = path(name) else { return; };
+    let content = serde_json::to_string(value).unwrap();
+
+    // try to write file as a "fire and forget" as it's result is irrelevant here
+    tokio::spawn(async move {
+        let _ = tokio::fs::write(path, content).await;
+    });
+}
+
+/// like [`write`], but write the file before returning. return error message on failure.
+pub fn write_now<T: serde::Serialize>(name: &str, value: &T) -> Result<(), String> {
+    let path = path(name).ok_or("couldn't get path to data dir")?;
+    let content = serde_json::to_string(value).unwrap();
+    std::fs::write(&path, content).map_err(|error| format!("couldn't write {}: {error}", path.display()))
+}
+
+/// return error message if `name` can't be used for an item stored by name, like a program or scene
+pub fn check_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() || name.len() > 64 {
+        return Err("name must have 1 to 64 characters");
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err("name must only contain ASCII letters, digits, - and _");
+    }
+    Ok(())
+}
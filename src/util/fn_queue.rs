@@ -1,21 +1,51 @@
 use tokio::sync::Mutex;
-use crate::util::govee_api::SetState;
-use std::{sync::Arc, collections::VecDeque};
+use chrono::NaiveDateTime;
+use crate::util::{timeday, govee_queue::{GoveeQueue, Shadow}};
+use std::{sync::{Arc, atomic::{AtomicU64, Ordering}}, collections::VecDeque};
+
+/// id of the last enqueued function
+static LAST_JOB: AtomicU64 = AtomicU64::new(0);
+/// when all states of the govee queue are estimated to be set, as of the last `call_all`
+static IDLE_AT: std::sync::Mutex<Option<NaiveDateTime>> = std::sync::Mutex::new(None);
+/// state of the lamp after all states of the govee queue are set, as of the last `call_all`
+static EXPECTED: std::sync::Mutex<Shadow> = std::sync::Mutex::new(Shadow { power: None, brightness: None, color: None });
 
 /// take `govee_queue` as argument
-pub type Element = Arc<dyn Fn(&mut VecDeque<SetState>) + Send + Sync>;
+pub type Element = Arc<dyn Fn(&mut GoveeQueue) + Send + Sync>;
 pub type Queue = Arc<Mutex<VecDeque<Element>>>;
 
 /// call and then remove each function, starting from the front.
 #[allow(clippy::significant_drop_tightening)]
-pub async fn call_all(function_queue: &Queue, govee_queue: &mut VecDeque<SetState>) {
+pub async fn call_all(function_queue: &Queue, govee_queue: &mut GoveeQueue) {
     let mut function_queue = function_queue.lock().await;
     // call all functions
     while !function_queue.is_empty() {
         function_queue.pop_front().unwrap()(govee_queue);
     }
+    *IDLE_AT.lock().unwrap() = Some(govee_queue.idle_at(timeday::local_now()));
+    *EXPECTED.lock().unwrap() = govee_queue.expected();
+}
+
+/// state of the lamp after all queued states are set, as far as it is known
+pub fn expected_state() -> Shadow {
+    *EXPECTED.lock().unwrap()
+}
+
+/// append `function` and return its id, which is unique until the server restarts.
+/// ids ascend in the order the functions are called.
+pub async fn enqueue(function_queue: &Queue, function: Element) -> u64 {
+    let mut function_queue = function_queue.lock().await;
+    function_queue.push_back(function);
+    // while holding the lock, so no other function is appended in between
+    LAST_JOB.fetch_add(1, Ordering::Relaxed) + 1
 }
 
-pub async fn enqueue(function_queue: &Queue, function: Element) {
-    function_queue.lock().await.push_back(function);
+/// estimate when `states` appended to the govee queue now will be set.
+/// they are set after the states queued before, unless `replace` clears those.
+pub fn estimate_done_at(states: usize, replace: bool) -> NaiveDateTime {
+    use crate::constants::govee::{API_REQUEST_INTERVAL, AVG_SET_STATE_DURATION};
+    let now = timeday::local_now();
+    let start = if replace { now } else { IDLE_AT.lock().unwrap().map_or(now, |idle_at| idle_at.max(now)) };
+    let slot = chrono::TimeDelta::from_std(API_REQUEST_INTERVAL + AVG_SET_STATE_DURATION).unwrap();
+    start + slot * i32::try_from(states).unwrap_or(i32::MAX)
 }
\ No newline at end of file
@@ -1,31 +1,77 @@
+use std::sync::OnceLock;
 use crate::constants;
 use crate::util::api_request::{Method, send};
-use crate::util::govee_secrets::{api_key, device, model};
+use crate::util::{kelvin, govee_secrets::{self, api_key, device, model}};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SetState {
     Color((u8, u8, u8)),
+    /// white light in Kelvin, see [`color_temperature_range`]
+    ColorTemperature(u16),
     /// from 1 to 100
     Brightness(u8),
     Power(bool)
 }
 
 #[derive(
-    Debug,
+    Debug, Clone, Copy, PartialEq, Eq,
     serde::Serialize, // to axum::Json
     utoipa::ToSchema  // to display in swagger-ui
 )]
 pub struct GetState {
-    /// each value is from 0 to 255
+    /// each value is from 0 to 255.
+    /// approximated from the color temperature if the lamp shows white light.
     #[schema(min_items = 3, max_items = 3)]
     pub rgb_color: (u8, u8, u8),
+    /// in Kelvin, if the lamp shows white light
+    pub color_temperature: Option<u16>,
     /// from 1 to 100
     #[schema(minimum = 1, maximum = 100)]
     pub brightness: u8,
     pub power: bool
 }
 
-/// limits brightness from 1 to 100.
+/// color temperature range in Kelvin of the configured lamp, as reported by the Govee device list
+static COLOR_TEMPERATURE_RANGE: OnceLock<(u16, u16)> = OnceLock::new();
+
+/// color temperatures in Kelvin supported by the configured lamp.
+/// estimated by its model until [`fetch_color_temperature_range`] succeeded.
+pub fn color_temperature_range() -> std::ops::RangeInclusive<u16> {
+    use constants::govee::{COLOR_TEMPERATURE_RANGES, DEFAULT_COLOR_TEMPERATURE_RANGE};
+    let (min, max) = COLOR_TEMPERATURE_RANGE.get().copied().unwrap_or_else(|| {
+        let model = govee_secrets::INSTANCE.get().map(|secrets| secrets.model.as_str());
+        COLOR_TEMPERATURE_RANGES.iter()
+            .find(|(known_model, _)| Some(*known_model) == model)
+            .map_or(DEFAULT_COLOR_TEMPERATURE_RANGE, |(_, range)| *range)
+    });
+    min ..= max
+}
+
+/// get the color temperature range of the configured lamp from the Govee device list.
+/// dependent on govee api.
+pub async fn fetch_color_temperature_range() -> Result<(u16, u16), ()> {
+    let url = "https://developer-api.govee.com/v1/devices";
+    let Ok(json) = send(Method::Get, url, Some(vec![("Govee-API-Key", &api_key())])).await else {
+        return Err(());
+    };
+    let Some(range) = parse_color_temperature_range(&json, &device(), &model()) else {
+        println!("got no color temperature range for the lamp in device list {json}");
+        return Err(());
+    };
+    let _ = COLOR_TEMPERATURE_RANGE.set(range);
+    Ok(range)
+}
+
+/// parse the color temperature range of `device` from the response of a GET devices call
+fn parse_color_temperature_range(json: &serde_json::Value, device: &str, model: &str) -> Option<(u16, u16)> {
+    let lamp = json["data"]["devices"].as_array()?.iter()
+        .find(|lamp| lamp["device"].as_str() == Some(device) && lamp["model"].as_str() == Some(model))?;
+    let range = &lamp["properties"]["colorTem"]["range"];
+    let kelvin = |name: &str| range[name].as_u64().and_then(|v| u16::try_from(v).ok());
+    Some((kelvin("min")?, kelvin("max")?)).filter(|(min, max)| min <= max)
+}
+
+/// limits brightness from 1 to 100 and color temperature to [`color_temperature_range`].
 /// returns success.
 /// dependent on govee api.
 /// only prints state and waits a little instead of setting it if `cfg!(feature = "govee_debug")`.
@@ -35,7 +81,7 @@ pub async fn set_state(state: SetState) -> bool {
 
     if cfg!(feature = "govee_debug") {
         // emulate request by waiting a bit
-        std::thread::sleep(constants::govee::AVG_SET_STATE_DURATION);
+        tokio::time::sleep(constants::govee::AVG_SET_STATE_DURATION).await;
         return true;
     }
 
@@ -43,6 +89,7 @@ pub async fn set_state(state: SetState) -> bool {
 
     let cmd_name = match state {
         SetState::Color(_) => "color",
+        SetState::ColorTemperature(_) => "colorTem",
         SetState::Brightness(_) => "brightness",
         SetState::Power(_) => "turn"
     };
@@ -53,6 +100,10 @@ pub async fn set_state(state: SetState) -> bool {
             "g": color.1,
             "b": color.2,
         }),
+        SetState::ColorTemperature(kelvin) => {
+            let range = color_temperature_range();
+            kelvin.clamp(*range.start(), *range.end()).into()
+        },
         SetState::Brightness(brightness) => brightness.clamp(1, 100).into(),
         SetState::Power(power) => (if power { "on" } else { "off" }).into()
     };
@@ -88,17 +139,76 @@ pub async fn get_state() -> Result<GetState, ()> {
         return Err(());
     };
 
-    let data = &json["data"]["properties"];
-    let state = GetState {
-        rgb_color: (
-            data[3]["color"]["r"].as_u64().unwrap().try_into().unwrap(),
-            data[3]["color"]["g"].as_u64().unwrap().try_into().unwrap(),
-            data[3]["color"]["b"].as_u64().unwrap().try_into().unwrap()
-        ),
-        brightness: data[2]["brightness"].as_u64().unwrap().try_into().unwrap(),
-        power: data[1]["powerState"].as_str().unwrap() == "on"
+    let Some(state) = parse_state(&json) else {
+        println!("got unexpected state response {json}");
+        return Err(());
     };
 
     println!("got state {state:?}");
     Ok(state)
-}
\ No newline at end of file
+}
+
+/// parse response of a GET device state call.
+/// properties are found by name, because their order and presence depend on the color mode.
+fn parse_state(json: &serde_json::Value) -> Option<GetState> {
+    let properties = json["data"]["properties"].as_array()?;
+    let property = |name: &str| properties.iter().find_map(|property| property.get(name));
+    let color_value = |name| property("color")?[name].as_u64().and_then(|v| u8::try_from(v).ok());
+
+    let color_temperature = property("colorTem")
+        .and_then(serde_json::Value::as_u64)
+        .and_then(|v| u16::try_from(v).ok())
+        // 0 means the lamp shows a color
+        .filter(|kelvin| *kelvin > 0);
+    let rgb_color = match (color_value("r"), color_value("g"), color_value("b")) {
+        (Some(r), Some(g), Some(b)) => (r, g, b),
+        _ => kelvin::to_rgb(color_temperature?)
+    };
+
+    Some(GetState {
+        rgb_color,
+        color_temperature,
+        brightness: property("brightness")?.as_u64().and_then(|v| u8::try_from(v).ok())?,
+        power: property("powerState")?.as_str()? == "on"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_state_by_name() {
+        let color = serde_json::json!({ "data": { "properties": [
+            { "online": true },
+            { "powerState": "on" },
+            { "brightness": 42 },
+            { "color": { "r": 255, "g": 100, "b": 0 } }
+        ] } });
+        let state = parse_state(&color).unwrap();
+        assert_eq!((state.rgb_color, state.color_temperature, state.brightness, state.power), ((255, 100, 0), None, 42, true));
+
+        // white light in different order
+        let white = serde_json::json!({ "data": { "properties": [
+            { "colorTem": 6600 },
+            { "brightness": 1 },
+            { "powerState": "off" }
+        ] } });
+        let state = parse_state(&white).unwrap();
+        assert_eq!((state.rgb_color, state.color_temperature, state.brightness, state.power), ((255, 255, 255), Some(6600), 1, false));
+
+        assert!(parse_state(&serde_json::json!({ "data": { "properties": [{ "powerState": "on" }] } })).is_none());
+    }
+
+    #[test]
+    fn parse_color_temperature_range_of_device() {
+        let devices = serde_json::json!({ "data": { "devices": [
+            { "device": "AA:BB", "model": "H6008", "properties": { "colorTem": { "range": { "min": 2700, "max": 6500 } } } },
+            { "device": "CC:DD", "model": "H6159", "properties": { "colorTem": { "range": { "min": 2000, "max": 9000 } } } },
+            { "device": "EE:FF", "model": "H6001", "supportCmds": ["turn", "brightness", "color"] }
+        ] } });
+        assert_eq!(parse_color_temperature_range(&devices, "CC:DD", "H6159"), Some((2000, 9000)));
+        assert_eq!(parse_color_temperature_range(&devices, "CC:DD", "H6008"), None);
+        assert_eq!(parse_color_temperature_range(&devices, "EE:FF", "H6001"), None);
+    }
+}
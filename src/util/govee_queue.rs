@@ -0,0 +1,328 @@
+use std::{collections::VecDeque, sync::atomic::{AtomicU32, Ordering}, time::Duration};
+use chrono::{NaiveDateTime, TimeDelta};
+use crate::util::{kelvin, govee_api::{GetState, SetState}};
+
+/// last id given to a sequence
+static LAST_SEQUENCE: AtomicU32 = AtomicU32::new(0);
+/// last id given to a group
+static LAST_GROUP: AtomicU32 = AtomicU32::new(0);
+
+/// role of an entry in a group of entries, like the states of a reminder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Group {
+    /// shows the group, like turning on a reminder
+    Shows(u32),
+    /// left out unless an entry showing the group was set, like turning off after a reminder
+    After(u32)
+}
+
+/// id for a new group of entries
+pub fn new_group() -> u32 {
+    LAST_GROUP.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// state to set, optionally only within a time window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    pub state: SetState,
+    /// not set before this time
+    pub not_before: Option<NaiveDateTime>,
+    /// left out if it could not be set until this time
+    pub expires_at: Option<NaiveDateTime>,
+    /// entries of the same sequence are planned one after another, like the steps of a sunrise.
+    /// a late entry is left out if a later entry of the same sequence and kind is due already.
+    pub sequence: Option<u32>,
+    pub group: Option<Group>
+}
+
+impl From<SetState> for Entry {
+    fn from(state: SetState) -> Self {
+        Self { state, not_before: None, expires_at: None, sequence: None, group: None }
+    }
+}
+
+impl Entry {
+    /// `state` that is left out if it could not be set until `expires_at`
+    pub const fn expiring(state: SetState, expires_at: NaiveDateTime) -> Self {
+        Self { state, not_before: None, expires_at: Some(expires_at), sequence: None, group: None }
+    }
+}
+
+/// what is known about the state of the lamp, without fetching it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Shadow {
+    pub power: Option<bool>,
+    pub brightness: Option<u8>,
+    /// last `SetState::Color` or `SetState::ColorTemperature`
+    pub color: Option<SetState>
+}
+
+impl From<&GetState> for Shadow {
+    fn from(state: &GetState) -> Self {
+        Self {
+            power: Some(state.power),
+            brightness: Some(state.brightness),
+            color: Some(state.color_temperature.map_or(SetState::Color(state.rgb_color), SetState::ColorTemperature))
+        }
+    }
+}
+
+impl Shadow {
+    /// `self` after setting `state`
+    #[must_use]
+    pub const fn apply(mut self, state: SetState) -> Self {
+        match state {
+            SetState::Power(power) => self.power = Some(power),
+            SetState::Brightness(brightness) => self.brightness = Some(brightness),
+            SetState::Color(_) | SetState::ColorTemperature(_) => self.color = Some(state)
+        }
+        self
+    }
+
+    /// `self` with unknown values taken from `state`, e.g. a fetched older state
+    #[must_use]
+    pub fn or(self, state: &GetState) -> Self {
+        let fetched = Self::from(state);
+        Self {
+            power: self.power.or(fetched.power),
+            brightness: self.brightness.or(fetched.brightness),
+            color: self.color.or(fetched.color)
+        }
+    }
+
+    /// complete state, none if any value is unknown
+    pub fn state(&self) -> Option<GetState> {
+        let (rgb_color, color_temperature) = match self.color? {
+            SetState::ColorTemperature(kelvin) => (kelvin::to_rgb(kelvin), Some(kelvin)),
+            SetState::Color(rgb_color) => (rgb_color, None),
+            SetState::Brightness(_) | SetState::Power(_) => return None
+        };
+        Some(GetState { rgb_color, color_temperature, brightness: self.brightness?, power: self.power? })
+    }
+}
+
+/// queue of states of which the first one will be used for a Govee API call each iteration
+#[derive(Debug, Default)]
+pub struct GoveeQueue {
+    entries: VecDeque<Entry>,
+    /// state of the lamp after the states set so far
+    set: Shadow,
+    /// last group an entry showing it was set for
+    shown_group: Option<u32>
+}
+
+impl GoveeQueue {
+    pub fn push_back(&mut self, entry: impl Into<Entry>) {
+        self.entries.push_back(entry.into());
+    }
+
+    /// replace what is known about the lamp with its fetched `state`
+    pub fn set_known(&mut self, state: &GetState) {
+        self.set = Shadow::from(state);
+    }
+
+    /// state of the lamp after all queued states are set, as far as it is known
+    pub fn expected(&self) -> Shadow {
+        self.entries.iter().fold(self.set, |shadow, entry| shadow.apply(entry.state))
+    }
+
+    /// append states planned at their offset after `start` as a new sequence. return its id.
+    pub fn push_sequence(&mut self, start: NaiveDateTime, steps: impl IntoIterator<Item = (Duration, SetState)>) -> u32 {
+        let sequence = LAST_SEQUENCE.fetch_add(1, Ordering::Relaxed) + 1;
+        self.entries.extend(steps.into_iter().map(|(offset, state)| Entry {
+            state,
+            not_before: TimeDelta::from_std(offset).ok().map(|offset| start + offset),
+            expires_at: None,
+            sequence: Some(sequence),
+            group: None
+        }));
+        sequence
+    }
+
+    /// remove the entries of `sequence` which were not set yet, keeping all others. return how many were removed.
+    pub fn remove_sequence(&mut self, sequence: u32) -> usize {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.sequence != Some(sequence));
+        len - self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// states from front to back
+    #[cfg(test)]
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &SetState> {
+        self.entries.iter().map(|entry| &entry.state)
+    }
+
+    /// estimated time all entries are set, if setting them starts at `now`
+    pub fn idle_at(&self, now: NaiveDateTime) -> NaiveDateTime {
+        use crate::constants::govee::{API_REQUEST_INTERVAL, AVG_SET_STATE_DURATION};
+        let slot = TimeDelta::from_std(API_REQUEST_INTERVAL + AVG_SET_STATE_DURATION).unwrap();
+        self.entries.iter().fold(now, |at, entry| entry.not_before.map_or(at, |not_before| at.max(not_before)) + slot)
+    }
+
+    /// remove the front state after it was set
+    pub fn pop_front(&mut self) -> Option<SetState> {
+        let entry = self.entries.pop_front()?;
+        if let Some(Group::Shows(group)) = entry.group {
+            self.shown_group = Some(group);
+        }
+        self.set = self.set.apply(entry.state);
+        Some(entry.state)
+    }
+
+    /// state to set next at `now`, none if the queue is empty or its front is planned for later.
+    /// leaves out expired entries at the front, and entries of a sequence which were superseded while waiting,
+    /// so a delayed queue catches up instead of stretching e.g. a sunrise.
+    /// entries following a group are left out if no entry showing it was set.
+    pub fn front_due(&mut self, now: &NaiveDateTime) -> Option<SetState> {
+        while let Some(front) = self.entries.front() {
+            let expired = front.expires_at.is_some_and(|expires_at| expires_at < *now);
+            let superseded = front.sequence.is_some() && self.entries.iter().skip(1)
+                .filter(|later| later.sequence == front.sequence && same_kind(front.state, later.state))
+                .any(|later| later.not_before.is_some_and(|not_before| not_before <= *now));
+            // groups are queued at once, so entries showing it are set before
+            let not_shown = matches!(front.group, Some(Group::After(group)) if self.shown_group != Some(group));
+            if !expired && !superseded && !not_shown {
+                break;
+            }
+            let reason = if expired { "expired" } else if superseded { "late" } else { "not shown group's" };
+            println!("leaving out {reason} {:?}", front.state);
+            self.entries.pop_front();
+        }
+        self.entries.front()
+            .filter(|entry| entry.not_before.is_none_or(|not_before| not_before <= *now))
+            .map(|entry| entry.state)
+    }
+}
+
+/// whether the states change the same property of the lamp
+const fn same_kind(a: SetState, b: SetState) -> bool {
+    matches!(
+        (a, b),
+        (SetState::Brightness(_), SetState::Brightness(_)) |
+        (SetState::Power(_), SetState::Power(_)) |
+        (SetState::Color(_) | SetState::ColorTemperature(_), SetState::Color(_) | SetState::ColorTemperature(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(6, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn late_sequence_catches_up() {
+        let start = start();
+        let mut queue = GoveeQueue::default();
+        queue.push_sequence(start, [
+            (Duration::from_secs(0), SetState::Brightness(1)),
+            (Duration::from_secs(6), SetState::Color((255, 0, 0))),
+            (Duration::from_secs(12), SetState::Brightness(2)),
+            (Duration::from_secs(18), SetState::Brightness(3)),
+            (Duration::from_secs(24), SetState::ColorTemperature(2000)),
+        ]);
+        queue.push_back(SetState::Power(false));
+
+        // too early, then in time
+        assert_eq!(queue.front_due(&(start - TimeDelta::seconds(1))), None);
+        assert_eq!(queue.front_due(&start), Some(SetState::Brightness(1)));
+        queue.pop_front();
+        // 20s late: color is not superseded yet, but brightness 2 is
+        assert_eq!(queue.front_due(&(start + TimeDelta::seconds(20))), Some(SetState::Color((255, 0, 0))));
+        queue.pop_front();
+        assert_eq!(queue.front_due(&(start + TimeDelta::seconds(20))), Some(SetState::Brightness(3)));
+        queue.pop_front();
+        // the last entry of a kind in a sequence is not left out, and neither are entries without time
+        assert_eq!(queue.front_due(&(start + TimeDelta::hours(1))), Some(SetState::ColorTemperature(2000)));
+        queue.pop_front();
+        assert_eq!(queue.front_due(&(start + TimeDelta::hours(1))), Some(SetState::Power(false)));
+    }
+
+    #[test]
+    fn expired_entries_are_left_out() {
+        let start = start();
+        let mut queue = GoveeQueue::default();
+        queue.push_back(Entry::expiring(SetState::Color((255, 100, 0)), start + TimeDelta::minutes(2)));
+        queue.push_back(Entry::expiring(SetState::Brightness(100), start + TimeDelta::minutes(2)));
+        queue.push_back(SetState::Power(false));
+
+        assert_eq!(queue.front_due(&(start + TimeDelta::minutes(2))), Some(SetState::Color((255, 100, 0))));
+        assert_eq!(queue.front_due(&(start + TimeDelta::minutes(10))), Some(SetState::Power(false)));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn entries_after_group_need_it_shown() {
+        let start = start();
+        let mut queue = GoveeQueue::default();
+        for _ in 0 .. 2 {
+            let group = new_group();
+            let on = Entry::expiring(SetState::Power(true), start + TimeDelta::minutes(2));
+            queue.push_back(Entry { group: Some(Group::Shows(group)), ..on });
+            queue.push_back(Entry { group: Some(Group::After(group)), ..Entry::from(SetState::Power(false)) });
+        }
+
+        // shown in time
+        assert_eq!(queue.front_due(&start), Some(SetState::Power(true)));
+        queue.pop_front();
+        assert_eq!(queue.front_due(&start), Some(SetState::Power(false)));
+        queue.pop_front();
+        // too late to show, so it doesn't turn off either
+        assert_eq!(queue.front_due(&(start + TimeDelta::minutes(10))), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn expected_state_includes_queued_states() {
+        let mut queue = GoveeQueue::default();
+        assert_eq!(queue.expected().state(), None);
+        queue.set_known(&GetState { rgb_color: (1, 2, 3), color_temperature: None, brightness: 40, power: true });
+        // like the nightlamp turning off in the same minute a sunrise starts
+        queue.push_back(SetState::Power(false));
+        assert_eq!(queue.expected().power, Some(false));
+        queue.pop_front();
+        queue.push_back(SetState::ColorTemperature(2700));
+        let expected = queue.expected().state().unwrap();
+        assert_eq!((expected.color_temperature, expected.brightness, expected.power), (Some(2700), 40, false));
+        // clearing the queue keeps what was set
+        queue.clear();
+        assert_eq!(queue.expected().color, Some(SetState::Color((1, 2, 3))));
+    }
+
+    #[test]
+    fn sequences_do_not_supersede_each_other() {
+        let start = start();
+        let mut queue = GoveeQueue::default();
+        queue.push_sequence(start, [(Duration::from_secs(0), SetState::Brightness(1))]);
+        queue.push_sequence(start, [(Duration::from_secs(0), SetState::Brightness(2))]);
+        assert_eq!(queue.front_due(&(start + TimeDelta::hours(1))), Some(SetState::Brightness(1)));
+    }
+
+    #[test]
+    fn removing_a_sequence_keeps_other_entries() {
+        let start = start();
+        let mut queue = GoveeQueue::default();
+        queue.push_back(SetState::Power(true));
+        let sequence = queue.push_sequence(start, [
+            (Duration::from_secs(0), SetState::Brightness(1)),
+            (Duration::from_secs(60), SetState::Brightness(2))
+        ]);
+        queue.push_back(SetState::Power(false));
+        assert_eq!(queue.remove_sequence(sequence), 2);
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), vec![SetState::Power(true), SetState::Power(false)]);
+    }
+}
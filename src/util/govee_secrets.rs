@@ -1,4 +1,3 @@
-use crate::constants;
 use std::sync::OnceLock; // thread-safe and can only be written to once
 
 // global instance which will receive values from config file
@@ -21,19 +20,10 @@ pub struct Struct {
 
 /// panics with error messages if valid `Struct` can't be retrieved
 pub fn from_file() -> Struct {
-    let mut path = dirs_next::config_dir()
-        .expect("path to config file could not be determined, which means your operating system is not supported.\n");
-    path.push(constants::CONFIG_FILE_NAME);
+    let (path, yaml_config) = crate::util::config::read_file();
 
-    // read file contents
-    let yaml_config = std::fs::read_to_string(path.clone()).unwrap_or_else( |_| panic!(
-        "config file could not be read from {}.\nsee the README for a template.\n",
-        path.to_str().unwrap()
-    ));
-    
     // parse yaml string to struct
     serde_yaml::from_str(&yaml_config).unwrap_or_else(|_| panic!(
-        "config file at {} could not be parsed.\nsee the README for a template.\n",
-        path.to_str().unwrap()
+        "config file at {path} could not be parsed.\nsee the README for a template.\n"
     ))
 }
\ No newline at end of file
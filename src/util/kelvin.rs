@@ -0,0 +1,43 @@
+/// approximate RGB color of white light with the given color temperature in Kelvin.
+/// based on the curve fit by Tanner Helland, valid from 1000 K to 40000 K.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn to_rgb(kelvin: u16) -> (u8, u8, u8) {
+    let temperature = f64::from(kelvin.clamp(1000, 40000)) / 100.0;
+    let channel = |value: f64| value.clamp(0.0, 255.0).round() as u8;
+
+    let red = if temperature <= 66.0 {
+        255.0
+    } else {
+        329.698_727_446 * (temperature - 60.0).powf(-0.133_204_759_2)
+    };
+    let green = if temperature <= 66.0 {
+        99.470_802_586_1_f64.mul_add(temperature.ln(), -161.119_568_166_1)
+    } else {
+        288.122_169_528_3 * (temperature - 60.0).powf(-0.075_514_849_2)
+    };
+    let blue = if temperature >= 66.0 {
+        255.0
+    } else if temperature <= 19.0 {
+        0.0
+    } else {
+        138.517_731_223_1_f64.mul_add((temperature - 10.0).ln(), -305.044_792_730_7)
+    };
+
+    (channel(red), channel(green), channel(blue))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warm_to_cold() {
+        // candle light is orange, daylight is almost white
+        assert_eq!(to_rgb(1900), (255, 132, 0));
+        assert_eq!(to_rgb(6600), (255, 255, 255));
+        assert!(to_rgb(10000).0 < to_rgb(10000).2, "cold light should be blueish");
+
+        let blues = (10 ..= 66).map(|hundreds| to_rgb(hundreds * 100).2).collect::<Vec<_>>();
+        assert!(blues.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+}
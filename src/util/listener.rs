@@ -0,0 +1,170 @@
+use std::{net::{IpAddr, SocketAddr}, path::PathBuf, sync::{Arc, RwLock}, time::SystemTime};
+use serde::Deserialize;
+use tokio::{net::{TcpListener, TcpStream}, sync::mpsc};
+use tokio_rustls::{TlsAcceptor, server::TlsStream, rustls::{self, pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject}}};
+use crate::constants::{self, net::{TLS_HANDSHAKE_TIMEOUT, TLS_RELOAD_INTERVAL}};
+
+/// TLS acceptor that is replaced when the certificate or key change
+pub type SharedAcceptor = Arc<RwLock<TlsAcceptor>>;
+
+/// PEM files to serve HTTPS with
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsFiles {
+    /// certificate chain, starting with the certificate of the server
+    pub cert: PathBuf,
+    /// private key of the certificate
+    pub key: PathBuf
+}
+
+impl TlsFiles {
+    /// read certificate and key into a TLS acceptor
+    pub fn acceptor(&self) -> Result<TlsAcceptor, String> {
+        let certs = CertificateDer::pem_file_iter(&self.cert)
+            .and_then(Iterator::collect::<Result<Vec<_>, _>>)
+            .map_err(|error| format!("certificate {} could not be read: {error}", self.cert.display()))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key)
+            .map_err(|error| format!("key {} could not be read: {error}", self.key.display()))?;
+        // explicit provider, so it doesn't matter which ones other dependencies enable
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::aws_lc_rs::default_provider()))
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|error| format!("certificate and key could not be used: {error}"))?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+
+    /// last modification of certificate and key
+    fn modified(&self) -> Option<(SystemTime, SystemTime)> {
+        let modified = |path: &PathBuf| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        Some((modified(&self.cert)?, modified(&self.key)?))
+    }
+}
+
+/// replace the acceptor whenever certificate or key change, e.g. after renewing them.
+/// keeps the previous acceptor if the new files can't be used.
+pub fn reload_on_change(files: TlsFiles, acceptor: SharedAcceptor) {
+    tokio::spawn(async move {
+        let mut last_modified = files.modified();
+        let mut interval = tokio::time::interval(TLS_RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            let modified = files.modified();
+            if modified.is_none() || modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+            match files.acceptor() {
+                Ok(new_acceptor) => {
+                    *acceptor.write().unwrap() = new_acceptor;
+                    println!("WEB: reloaded TLS certificate {}", files.cert.display());
+                },
+                Err(message) => println!("WEB: {message}, keeping previous TLS certificate...")
+            }
+        }
+    });
+}
+
+/// bind TCP listener to `address`.
+/// IPv6 addresses only accept IPv6, so "0.0.0.0:9000" and "[::]:9000" can be used together.
+pub fn bind(address: SocketAddr) -> std::io::Result<TcpListener> {
+    use socket2::{Domain, Socket, Type};
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, None)?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
+}
+
+/// listener serving HTTPS, with handshakes done concurrently in the background
+pub struct TlsListener {
+    local_address: SocketAddr,
+    streams: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>
+}
+
+impl TlsListener {
+    pub fn new(mut tcp: TcpListener, acceptor: SharedAcceptor) -> std::io::Result<Self> {
+        let local_address = tcp.local_addr()?;
+        let (sender, streams) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                // retries on errors
+                let (stream, address) = axum::serve::Listener::accept(&mut tcp).await;
+                let acceptor = acceptor.read().unwrap().clone();
+                let sender = sender.clone();
+                // slow clients must not hold up others
+                tokio::spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => { let _ = sender.send((stream, address)).await; },
+                        Ok(Err(error)) => println!("WEB: TLS handshake with {address} failed: {error}"),
+                        Err(_) => println!("WEB: TLS handshake with {address} timed out")
+                    }
+                });
+            }
+        });
+        Ok(Self { local_address, streams })
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        // the sender lives as long as the accepting task, which never stops
+        self.streams.recv().await.expect("TLS connections should be accepted until the server stops")
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_address)
+    }
+}
+
+/// `subjectAltName` extension for openssl with `hosts` and localhost
+fn subject_alt_names(hosts: &[String]) -> String {
+    let mut names = vec![String::from("DNS:localhost"), String::from("IP:127.0.0.1"), String::from("IP:::1")];
+    for host in hosts {
+        let name = if host.parse::<IpAddr>().is_ok() { format!("IP:{host}") } else { format!("DNS:{host}") };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    format!("subjectAltName={}", names.join(","))
+}
+
+/// generate a self-signed certificate for `hosts` (names or IP addresses) and its key with openssl,
+/// next to the config file. existing files are not overwritten.
+pub fn generate_self_signed(hosts: &[String]) -> Result<TlsFiles, String> {
+    let dir = dirs_next::config_dir().ok_or("path to config directory could not be determined")?;
+    let files = TlsFiles { cert: dir.join(constants::CERT_FILE_NAME), key: dir.join(constants::KEY_FILE_NAME) };
+    if let Some(existing) = [&files.cert, &files.key].into_iter().find(|path| path.exists()) {
+        return Err(format!("{} exists already, delete it to generate a new certificate", existing.display()));
+    }
+    let status = std::process::Command::new("openssl")
+        .args(["req", "-x509", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:prime256v1", "-nodes"])
+        .args(["-days", "3650", "-subj", "/CN=lamp-server", "-addext", &subject_alt_names(hosts)])
+        .arg("-keyout").arg(&files.key)
+        .arg("-out").arg(&files.cert)
+        .status()
+        .map_err(|error| format!("openssl could not be run: {error}"))?;
+    if !status.success() {
+        return Err(format!("openssl failed with {status}"));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alt_names_of_hosts() {
+        let hosts = [String::from("lamp.local"), String::from("192.168.0.10"), String::from("fd00::2"), String::from("localhost")];
+        assert_eq!(
+            subject_alt_names(&hosts),
+            "subjectAltName=DNS:localhost,IP:127.0.0.1,IP:::1,DNS:lamp.local,IP:192.168.0.10,IP:fd00::2"
+        );
+    }
+}
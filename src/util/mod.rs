@@ -1,5 +1,15 @@
+pub mod cron;
+pub mod curve;
+pub mod reminder;
+pub mod data_file;
+pub mod kelvin;
+pub mod solar;
+pub mod config;
 pub mod timeday;
+pub mod calendar;
 pub mod fn_queue;
+pub mod listener;
 pub mod govee_api;
+pub mod govee_queue;
 pub mod api_request;
 pub mod govee_secrets;
\ No newline at end of file
@@ -0,0 +1,46 @@
+/// what a reminder does after its last pulse
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub enum ReminderAfterwards {
+    /// restore the state the lamp had before the reminder, or turn off if it is not known
+    Restore,
+    /// turn off
+    #[default]
+    Off,
+    /// leave the reminder on
+    Keep
+}
+
+/// how a reminder flashes. durations are in API slots of about 6.5 seconds, one state can be set per slot.
+/// the default shows the reminder scene for about 20 seconds and then turns off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(default)]
+pub struct ReminderPattern {
+    /// color instead of the one of the reminder scene
+    pub color: Option<(u8, u8, u8)>,
+    /// how often the reminder turns on
+    pub pulses: u8,
+    /// slots a pulse stays on, including the slots needed to turn it on
+    pub on_slots: u8,
+    /// slots between pulses, including the slot needed to turn off
+    pub off_slots: u8,
+    pub afterwards: ReminderAfterwards
+}
+
+impl Default for ReminderPattern {
+    fn default() -> Self {
+        Self { color: None, pulses: 1, on_slots: 3, off_slots: 1, afterwards: ReminderAfterwards::Off }
+    }
+}
+
+impl ReminderPattern {
+    /// return error message if pattern is invalid
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !(1 ..= 20).contains(&self.pulses) {
+            return Err("pulses has to be from 1 to 20");
+        }
+        if !(1 ..= 50).contains(&self.on_slots) || !(1 ..= 50).contains(&self.off_slots) {
+            return Err("on_slots and off_slots have to be from 1 to 50");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use itertools::Itertools;
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+
+/// position on earth, configured in the config file
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+pub struct Location {
+    /// in degrees, north is positive
+    pub latitude: f64,
+    /// in degrees, east is positive
+    pub longitude: f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub enum SolarEvent {
+    /// civil dawn, sun is 6° below the horizon in the morning
+    Dawn,
+    Sunrise,
+    Sunset,
+    /// civil dusk, sun is 6° below the horizon in the evening
+    Dusk
+}
+
+/// alternative to `TimeDay` that is relative to the sun at the configured location
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct SolarTime {
+    event: SolarEvent,
+    /// minutes to shift from `event`, e.g. -15 for 15 minutes before sunset
+    #[schema(minimum = -720, maximum = 720)]
+    offset_min: i16,
+    #[schema(example = json!(vec![0u8]))]
+    #[schema(minimum = 0, maximum = 6)]
+    /// array of 1 to 7 days (number from 0 to 6, see `TimeDay`) on which `event` happens
+    days: Vec<u8>
+}
+
+impl SolarTime {
+    pub const fn get_offset_min(&self) -> &i16 { &self.offset_min }
+    pub const fn get_days(&self) -> &Vec<u8> { &self.days }
+
+    /// local date/time of `event` shifted by `offset_min` on `date`.
+    /// none if `date` is not on one of `days` or `event` does not happen on that date (polar day/night).
+    pub fn on(&self, date: NaiveDate, location: &Location) -> Option<NaiveDateTime> {
+        let weekday = u8::try_from(date.weekday().num_days_from_monday()).unwrap();
+        if !self.days.contains(&weekday) {
+            return None;
+        }
+        event_time(self.event, date, location)
+            .map(|datetime| datetime + TimeDelta::minutes(self.offset_min.into()))
+    }
+
+    /// earliest date/time (with seconds set to 0) strictly after `after`.
+    /// none if there is none within the next 8 days.
+    pub fn next_after(&self, after: &NaiveDateTime, location: &Location) -> Option<NaiveDateTime> {
+        // start one day before to include negative offsets across midnight
+        (0 ..= 9)
+            .filter_map(|day_shift| self.on(after.date() + Days::new(day_shift) - Days::new(1), location))
+            .find(|datetime| datetime > after)
+    }
+}
+
+// format like sunset-15min@["Mo", "Tu"]
+impl std::fmt::Display for SolarTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "{:?}{:+}min@{:?}",
+            self.event,
+            self.offset_min,
+            self.days.iter().map(|d| {
+                let days = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+                days[*d as usize]
+            }).collect_vec()
+        ))
+    }
+}
+
+/// local date/time (based on `TIMEZONE` constant, seconds set to 0) of `event` on `date` at `location`.
+/// none if `event` does not happen on that date (polar day/night).
+/// uses the sunrise equation from the Almanac for Computers (1990), accurate to about a minute.
+#[allow(clippy::cast_possible_truncation)]
+pub fn event_time(event: SolarEvent, date: NaiveDate, location: &Location) -> Option<NaiveDateTime> {
+    use crate::constants::TIMEZONE;
+
+    // angle between zenith and sun
+    let zenith: f64 = match event {
+        // includes refraction and radius of the sun
+        SolarEvent::Sunrise | SolarEvent::Sunset => 90.833,
+        SolarEvent::Dawn | SolarEvent::Dusk => 96.0
+    };
+    let rising = matches!(event, SolarEvent::Dawn | SolarEvent::Sunrise);
+
+    let longitude_hour = location.longitude / 15.0;
+    let approximate_time = f64::from(date.ordinal()) + (if rising { 6.0 } else { 18.0 } - longitude_hour) / 24.0;
+
+    // mean anomaly and true longitude of the sun
+    let anomaly = 0.9856f64.mul_add(approximate_time, -3.289);
+    let true_longitude = (anomaly
+        + 1.916 * anomaly.to_radians().sin()
+        + 0.020 * (2.0 * anomaly).to_radians().sin()
+        + 282.634).rem_euclid(360.0);
+
+    // right ascension, in the same quadrant as the true longitude
+    let ascension = (0.91764 * true_longitude.to_radians().tan()).atan().to_degrees().rem_euclid(360.0);
+    let ascension = (ascension
+        + (true_longitude / 90.0).floor() * 90.0
+        - (ascension / 90.0).floor() * 90.0) / 15.0;
+
+    // declination of the sun
+    let sin_declination = 0.39782 * true_longitude.to_radians().sin();
+    let cos_declination = sin_declination.asin().cos();
+
+    // local hour angle
+    let latitude = location.latitude.to_radians();
+    let cos_hour_angle = sin_declination.mul_add(-latitude.sin(), zenith.to_radians().cos())
+        / (cos_declination * latitude.cos());
+    if !(-1.0 ..= 1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    let hour_angle = if rising { 360.0 - hour_angle } else { hour_angle } / 15.0;
+
+    let local_mean_time = 0.06571f64.mul_add(-approximate_time, hour_angle + ascension) - 6.622;
+    let universal_hours = (local_mean_time - longitude_hour).rem_euclid(24.0);
+
+    let utc = date.and_hms_opt(0, 0, 0).unwrap()
+        + TimeDelta::minutes((universal_hours * 60.0).round() as i64);
+    let local = Utc.from_utc_datetime(&utc).with_timezone(&TIMEZONE).naive_local();
+
+    // the computed time of day in UTC might be on a neighboring local date
+    Some(match local.date().cmp(&date) {
+        std::cmp::Ordering::Less => local + TimeDelta::days(1),
+        std::cmp::Ordering::Equal => local,
+        std::cmp::Ordering::Greater => local - TimeDelta::days(1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+
+    const BERLIN: Location = Location { latitude: 52.52, longitude: 13.405 };
+
+    /// assert that `datetime` is at most 3 minutes away from `date` at `hour`:`minute`
+    fn assert_near(datetime: Option<NaiveDateTime>, date: NaiveDate, hour: u32, minute: u32) {
+        let expected = date.and_time(NaiveTime::from_hms_opt(hour, minute, 0).unwrap());
+        let difference = (datetime.unwrap() - expected).num_minutes().abs();
+        assert!(difference <= 3, "expected about {expected}, got {datetime:?}");
+    }
+
+    #[test]
+    fn berlin_summer_solstice() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        assert_near(event_time(SolarEvent::Sunrise, date, &BERLIN), date, 4, 43);
+        assert_near(event_time(SolarEvent::Sunset, date, &BERLIN), date, 21, 33);
+        assert_near(event_time(SolarEvent::Dusk, date, &BERLIN), date, 22, 24);
+    }
+
+    #[test]
+    fn berlin_winter_solstice() {
+        let date = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        assert_near(event_time(SolarEvent::Dawn, date, &BERLIN), date, 7, 34);
+        assert_near(event_time(SolarEvent::Sunrise, date, &BERLIN), date, 8, 15);
+        assert_near(event_time(SolarEvent::Sunset, date, &BERLIN), date, 15, 54);
+    }
+
+    #[test]
+    fn polar_night() {
+        let tromso = Location { latitude: 69.65, longitude: 18.96 };
+        let date = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        assert!(event_time(SolarEvent::Sunrise, date, &tromso).is_none());
+    }
+
+    #[test]
+    fn offset_and_days() {
+        // friday
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let solar = SolarTime { event: SolarEvent::Sunset, offset_min: -15, days: vec![4] };
+        assert_near(solar.on(date, &BERLIN), date, 21, 18);
+        assert!(solar.on(date + Days::new(1), &BERLIN).is_none());
+    }
+}
@@ -1,4 +1,16 @@
 use itertools::Itertools;
+use chrono::{Datelike, Days, NaiveDateTime, NaiveTime, Timelike};
+
+/// current local date/time based on `TIMEZONE` constant
+pub fn local_now() -> NaiveDateTime {
+    use crate::constants::TIMEZONE;
+    chrono::Utc::now().with_timezone(&TIMEZONE).naive_local()
+}
+
+/// given date/time with seconds (and smaller units) set to 0
+pub fn truncate_to_minute(datetime: &NaiveDateTime) -> NaiveDateTime {
+    datetime.date().and_hms_opt(datetime.hour(), datetime.minute(), 0).unwrap()
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
 pub struct TimeDay {
@@ -32,17 +44,36 @@ impl TimeDay {
 
     /// current time and weekday based on `TIMEZONE` constant
     pub fn now() -> Self {
-        use chrono::{Utc, Timelike, Datelike};
-        use crate::constants::TIMEZONE;
+        Self::from_datetime(&local_now())
+    }
 
-        let now = Utc::now().with_timezone(&TIMEZONE);
+    /// time and weekday of given date/time
+    pub fn from_datetime(datetime: &NaiveDateTime) -> Self {
         Self::new(
-            now.hour().try_into().unwrap(),
-            now.minute().try_into().unwrap(),
-            vec![now.weekday().num_days_from_monday().try_into().unwrap()]
+            datetime.hour().try_into().unwrap(),
+            datetime.minute().try_into().unwrap(),
+            vec![datetime.weekday().num_days_from_monday().try_into().unwrap()]
         )
     }
 
+    /// whether given date/time is on one of `days` and has the same hour and minute
+    pub fn matches(&self, datetime: &NaiveDateTime) -> bool {
+        let other = Self::from_datetime(datetime);
+        self.days.contains(&other.days[0])
+        && self.hour == other.hour
+        && self.minute == other.minute
+    }
+
+    /// earliest date/time (with seconds set to 0) strictly after `after` that matches
+    pub fn next_after(&self, after: &NaiveDateTime) -> NaiveDateTime {
+        let time = NaiveTime::from_hms_opt(self.hour.into(), self.minute.into(), 0).unwrap();
+        // at most 7 days ahead, plus one for an earlier time on the same weekday
+        (0 ..= 7)
+            .map(|day_shift| (after.date() + Days::new(day_shift)).and_time(time))
+            .find(|candidate| candidate > after && self.matches(candidate))
+            .unwrap()
+    }
+
     pub const fn get_hour(&self) -> &u8 { &self.hour }
     pub const fn get_minute(&self) -> &u8 { &self.minute }
     pub const fn get_days(&self) -> &Vec<u8> { &self.days }
@@ -77,13 +108,17 @@ impl TimeDay {
 
     /// 0 to 23. panics if value is out of range.
     fn set_hour(&mut self, hour: u8) {
-        assert!(hour <= 23, "hour has to be <= 23, was {hour:?}");
+        if let Err(message) = check_hour(hour) {
+            panic!("{message}, was {hour:?}");
+        }
         self.hour = hour;
     }
 
     /// 0 to 59. panics if value is out of range.
     fn set_minute(&mut self, minute: u8) {
-        assert!(minute <= 59, "minute has to be <= 59, was {minute:?}");
+        if let Err(message) = check_minute(minute) {
+            panic!("{message}, was {minute:?}");
+        }
         self.minute = minute;
     }
 
@@ -97,13 +132,37 @@ impl TimeDay {
     /// 5 - saturday <br>
     /// 6 - sunday <br>
     fn set_days(&mut self, days: Vec<u8>) {
-        assert!(!days.is_empty(), "days must not be empty");
-        assert!(days.len() <= 7, "days must have <= 7 elements");
-        assert!(days.iter().any(|&d| d <= 6), "every day has to be <= 6, days were {days:?}");
+        if let Err(message) = check_days(&days) {
+            panic!("{message}, days were {days:?}");
+        }
         self.days = days.into_iter().unique().sorted().collect_vec();
     }
 }
 
+/// error message if `hour` is not from 0 to 23
+pub const fn check_hour(hour: u8) -> Result<(), &'static str> {
+    if hour > 23 { Err("hour has to be <= 23") } else { Ok(()) }
+}
+
+/// error message if `minute` is not from 0 to 59
+pub const fn check_minute(minute: u8) -> Result<(), &'static str> {
+    if minute > 59 { Err("minute has to be <= 59") } else { Ok(()) }
+}
+
+/// error message if `days` is empty, has > 7 elements or one of them is not from 0 to 6.
+/// also used for other types with days like `SolarTime`.
+pub fn check_days(days: &[u8]) -> Result<(), &'static str> {
+    if days.is_empty() {
+        Err("days must not be empty")
+    } else if days.len() > 7 {
+        Err("days must have <= 7 elements")
+    } else if days.iter().any(|&d| d > 6) {
+        Err("every day has to be <= 6")
+    } else {
+        Ok(())
+    }
+}
+
 // format like 15:20@["Mo", "Tu"]
 impl std::fmt::Display for TimeDay {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -122,6 +181,7 @@ impl std::fmt::Display for TimeDay {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::NaiveDate;
 
     #[test]
     fn simple_minute_shift() {
@@ -192,9 +252,33 @@ mod tests {
         assert_eq!(*timeday.get_days(), vec![0, 1, 2, 3]);
     }
 
+    #[test]
+    fn next_after_same_day() {
+        let timeday = TimeDay::new(7, 0, vec![0, 2]);
+        // monday
+        let after = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(6, 59, 30).unwrap();
+        let next = timeday.next_after(&after);
+        assert_eq!(next, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(7, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn next_after_next_week() {
+        let timeday = TimeDay::new(7, 0, vec![0]);
+        // monday, exactly at the matching time
+        let after = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(7, 0, 0).unwrap();
+        let next = timeday.next_after(&after);
+        assert_eq!(next, NaiveDate::from_ymd_opt(2024, 1, 8).unwrap().and_hms_opt(7, 0, 0).unwrap());
+    }
+
     #[test]
     fn unsorted_days() {
         let timeday = TimeDay::new(0, 0, vec![1, 2, 0, 3, 5, 6, 4]);
         assert_eq!(*timeday.get_days(), vec![0, 1, 2, 3, 4, 5, 6]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[should_panic(expected = "every day has to be <= 6")]
+    fn day_out_of_range() {
+        TimeDay::new(0, 0, vec![0, 7]);
+    }
+}